@@ -0,0 +1,11 @@
+DROP TABLE game_players;
+DROP TABLE games;
+
+ALTER TABLE users
+    DROP COLUMN games_played,
+    DROP COLUMN rating_volatility,
+    DROP COLUMN rating_deviation;
+
+ALTER TABLE users ALTER COLUMN rating DROP DEFAULT;
+ALTER TABLE users ALTER COLUMN rating TYPE INTEGER USING round(rating)::INTEGER;
+ALTER TABLE users ALTER COLUMN rating SET DEFAULT 0;
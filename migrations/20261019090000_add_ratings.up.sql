@@ -0,0 +1,29 @@
+ALTER TABLE users ALTER COLUMN rating DROP DEFAULT;
+ALTER TABLE users ALTER COLUMN rating TYPE DOUBLE PRECISION;
+ALTER TABLE users ALTER COLUMN rating SET DEFAULT 1500;
+UPDATE users SET rating = 1500;
+
+ALTER TABLE users
+    ADD COLUMN rating_deviation DOUBLE PRECISION NOT NULL DEFAULT 350,
+    ADD COLUMN rating_volatility DOUBLE PRECISION NOT NULL DEFAULT 0.06,
+    ADD COLUMN games_played INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE games (
+    id TEXT PRIMARY KEY,
+    winner_team SMALLINT NOT NULL,
+    team_a_eyes INTEGER NOT NULL,
+    team_b_eyes INTEGER NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE game_players (
+    game_id TEXT NOT NULL REFERENCES games (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id),
+    position TEXT NOT NULL,
+    team SMALLINT NOT NULL,
+    rating_before DOUBLE PRECISION NOT NULL,
+    rating_after DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (game_id, user_id)
+);
+
+CREATE INDEX idx_game_players_user ON game_players (user_id);
@@ -0,0 +1,11 @@
+CREATE TABLE game_histories (
+    game_id TEXT PRIMARY KEY,
+    -- player ids by seat: North, East, South, West
+    players TEXT[] NOT NULL,
+    rated BOOLEAN NOT NULL,
+    winner_team SMALLINT NOT NULL,
+    deals JSONB NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_game_histories_players ON game_histories USING GIN (players);
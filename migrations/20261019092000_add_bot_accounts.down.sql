@@ -0,0 +1,5 @@
+DROP TABLE api_tokens;
+
+ALTER TABLE users
+    DROP COLUMN owner_id,
+    DROP COLUMN is_bot;
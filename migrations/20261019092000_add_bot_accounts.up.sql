@@ -0,0 +1,16 @@
+ALTER TABLE users
+    ADD COLUMN is_bot BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN owner_id INTEGER REFERENCES users (id);
+
+-- Long-lived API tokens of bot accounts; only the SHA-256 of a token is kept.
+CREATE TABLE api_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_api_tokens_user ON api_tokens (user_id);
+CREATE INDEX idx_users_owner ON users (owner_id);
@@ -0,0 +1,3 @@
+DROP TABLE game_plays;
+DROP TABLE game_rounds;
+DROP TABLE game_records;
@@ -0,0 +1,42 @@
+-- Every game as it is played, written while the room runs.
+CREATE TABLE game_records (
+    id TEXT PRIMARY KEY,
+    -- player or bot ids by seat: North, East, South, West
+    seats TEXT[] NOT NULL,
+    seed BIGINT NOT NULL,
+    variant TEXT NOT NULL,
+    rated BOOLEAN NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ,
+    -- 'finished' for a played out or resigned game, otherwise why the room closed
+    end_reason TEXT,
+    winner_team SMALLINT,
+    team_a_eyes INTEGER,
+    team_b_eyes INTEGER
+);
+
+CREATE INDEX idx_game_records_seats ON game_records USING GIN (seats);
+
+CREATE TABLE game_rounds (
+    game_id TEXT NOT NULL REFERENCES game_records (id) ON DELETE CASCADE,
+    round SMALLINT NOT NULL,
+    trump TEXT NOT NULL,
+    trump_team SMALLINT NOT NULL,
+    team_a_points INTEGER NOT NULL,
+    team_b_points INTEGER NOT NULL,
+    -- NULL for an egg (60:60) or a round the game ended in
+    eyes_team SMALLINT,
+    eyes INTEGER NOT NULL,
+    PRIMARY KEY (game_id, round)
+);
+
+CREATE TABLE game_plays (
+    game_id TEXT NOT NULL,
+    round SMALLINT NOT NULL,
+    seq SMALLINT NOT NULL,
+    position TEXT NOT NULL,
+    rank TEXT NOT NULL,
+    suit TEXT NOT NULL,
+    PRIMARY KEY (game_id, round, seq),
+    FOREIGN KEY (game_id, round) REFERENCES game_rounds (game_id, round) ON DELETE CASCADE
+);
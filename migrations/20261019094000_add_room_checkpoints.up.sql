@@ -0,0 +1,8 @@
+-- Latest snapshot of every running room, to bring games back after a restart.
+CREATE TABLE room_checkpoints (
+    room_id TEXT PRIMARY KEY,
+    rated BOOLEAN NOT NULL,
+    seed BIGINT NOT NULL,
+    snapshot JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
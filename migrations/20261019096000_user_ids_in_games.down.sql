@@ -0,0 +1,11 @@
+UPDATE game_histories SET players = ARRAY(
+    SELECT COALESCE((SELECT u.username FROM users u WHERE u.id::TEXT = p.id), p.id)
+    FROM unnest(players) WITH ORDINALITY AS p (id, seat)
+    ORDER BY p.seat
+);
+
+UPDATE game_records SET seats = ARRAY(
+    SELECT COALESCE((SELECT u.username FROM users u WHERE u.id::TEXT = s.id), s.id)
+    FROM unnest(seats) WITH ORDINALITY AS s (id, seat)
+    ORDER BY s.seat
+);
@@ -0,0 +1,14 @@
+-- Players are identified by users.id now, not by username. Rewrite the
+-- usernames stored so far; a name shared by several accounts goes to the
+-- oldest one, which is who the old lookups picked as well.
+UPDATE game_histories SET players = ARRAY(
+    SELECT COALESCE((SELECT min(u.id)::TEXT FROM users u WHERE u.username = p.name), p.name)
+    FROM unnest(players) WITH ORDINALITY AS p (name, seat)
+    ORDER BY p.seat
+);
+
+UPDATE game_records SET seats = ARRAY(
+    SELECT COALESCE((SELECT min(u.id)::TEXT FROM users u WHERE u.username = s.name), s.name)
+    FROM unnest(seats) WITH ORDINALITY AS s (name, seat)
+    ORDER BY s.seat
+);
@@ -0,0 +1,55 @@
+CREATE TABLE games (
+    id TEXT PRIMARY KEY,
+    winner_team SMALLINT NOT NULL,
+    team_a_eyes INTEGER NOT NULL,
+    team_b_eyes INTEGER NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO games (id, winner_team, team_a_eyes, team_b_eyes, finished_at)
+SELECT r.id, r.winner_team, r.team_a_eyes, r.team_b_eyes, r.finished_at
+FROM game_records r
+WHERE EXISTS (SELECT 1 FROM game_players p WHERE p.game_id = r.id);
+
+ALTER TABLE game_players DROP CONSTRAINT game_players_game_id_fkey;
+ALTER TABLE game_players ADD CONSTRAINT game_players_game_id_fkey
+    FOREIGN KEY (game_id) REFERENCES games (id) ON DELETE CASCADE;
+
+CREATE TABLE game_histories (
+    game_id TEXT PRIMARY KEY,
+    -- player ids by seat: North, East, South, West
+    players TEXT[] NOT NULL,
+    rated BOOLEAN NOT NULL,
+    winner_team SMALLINT NOT NULL,
+    deals JSONB NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX idx_game_histories_players ON game_histories USING GIN (players);
+
+INSERT INTO game_histories (game_id, players, rated, winner_team, deals, finished_at)
+SELECT r.id, r.seats, r.rated, r.winner_team,
+    COALESCE((
+        SELECT jsonb_agg(jsonb_build_object(
+            'trump', d.trump,
+            'hands', COALESCE(d.hands, '{}'::JSONB),
+            'plays', COALESCE((
+                SELECT jsonb_agg(jsonb_build_array(p.position, jsonb_build_object('suit', p.suit, 'rank', p.rank)) ORDER BY p.seq)
+                FROM game_plays p WHERE p.game_id = d.game_id AND p.round = d.round
+            ), '[]'::JSONB)
+        ) ORDER BY d.round)
+        FROM game_rounds d WHERE d.game_id = r.id
+    ), '[]'::JSONB),
+    r.finished_at
+FROM game_records r
+WHERE r.end_reason = 'finished';
+
+DELETE FROM game_rounds WHERE trump_team IS NULL;
+DELETE FROM game_records WHERE seed IS NULL;
+ALTER TABLE game_rounds
+    ALTER COLUMN trump_team SET NOT NULL,
+    ALTER COLUMN team_a_points SET NOT NULL,
+    ALTER COLUMN team_b_points SET NOT NULL,
+    ALTER COLUMN eyes SET NOT NULL;
+ALTER TABLE game_records ALTER COLUMN seed SET NOT NULL;
+ALTER TABLE game_rounds DROP COLUMN hands;
@@ -0,0 +1,51 @@
+-- game_records, game_rounds and game_plays become the only record of a
+-- game. game_histories (deals for analysis) and games (rated results) are
+-- folded into them; game_players stays as the rating ledger of a game.
+
+-- Hands as dealt, which plays alone do not give for an unfinished deal
+ALTER TABLE game_rounds ADD COLUMN hands JSONB;
+
+-- Games finished before game_records existed did not keep these
+ALTER TABLE game_records ALTER COLUMN seed DROP NOT NULL;
+ALTER TABLE game_rounds
+    ALTER COLUMN trump_team DROP NOT NULL,
+    ALTER COLUMN team_a_points DROP NOT NULL,
+    ALTER COLUMN team_b_points DROP NOT NULL,
+    ALTER COLUMN eyes DROP NOT NULL;
+
+INSERT INTO game_records (id, seats, seed, variant, rated, started_at, finished_at, end_reason, winner_team, team_a_eyes, team_b_eyes)
+SELECT h.game_id, h.players, NULL, 'classic', h.rated, h.finished_at, h.finished_at, 'finished',
+    h.winner_team, g.team_a_eyes, g.team_b_eyes
+FROM game_histories h
+LEFT JOIN games g ON g.id = h.game_id
+ON CONFLICT (id) DO NOTHING;
+
+-- Rated games that somehow have no history: seats come from the ledger
+INSERT INTO game_records (id, seats, seed, variant, rated, started_at, finished_at, end_reason, winner_team, team_a_eyes, team_b_eyes)
+SELECT g.id,
+    ARRAY(
+        SELECT p.user_id::TEXT FROM game_players p WHERE p.game_id = g.id
+        ORDER BY array_position(ARRAY['North', 'East', 'South', 'West'], p.position)
+    ),
+    NULL, 'classic', true, g.finished_at, g.finished_at, 'finished', g.winner_team, g.team_a_eyes, g.team_b_eyes
+FROM games g
+ON CONFLICT (id) DO NOTHING;
+
+INSERT INTO game_rounds (game_id, round, trump, hands)
+SELECT h.game_id, d.idx - 1, d.deal->>'trump', d.deal->'hands'
+FROM game_histories h, jsonb_array_elements(h.deals) WITH ORDINALITY AS d (deal, idx)
+ON CONFLICT (game_id, round) DO UPDATE SET hands = EXCLUDED.hands;
+
+INSERT INTO game_plays (game_id, round, seq, position, rank, suit)
+SELECT h.game_id, d.idx - 1, p.idx - 1, p.play->>0, p.play->1->>'rank', p.play->1->>'suit'
+FROM game_histories h,
+    jsonb_array_elements(h.deals) WITH ORDINALITY AS d (deal, idx),
+    jsonb_array_elements(d.deal->'plays') WITH ORDINALITY AS p (play, idx)
+ON CONFLICT (game_id, round, seq) DO NOTHING;
+
+ALTER TABLE game_players DROP CONSTRAINT game_players_game_id_fkey;
+ALTER TABLE game_players ADD CONSTRAINT game_players_game_id_fkey
+    FOREIGN KEY (game_id) REFERENCES game_records (id) ON DELETE CASCADE;
+
+DROP TABLE game_histories;
+DROP TABLE games;
@@ -0,0 +1,2 @@
+-- Post-game review, computed on first request and kept with the game
+ALTER TABLE game_records ADD COLUMN analysis JSONB;
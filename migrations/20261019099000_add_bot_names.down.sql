@@ -0,0 +1,9 @@
+ALTER TABLE users
+    DROP CONSTRAINT users_telegram_id,
+    DROP CONSTRAINT users_bot_name;
+
+UPDATE users SET telegram_id = 'bot:' || bot_name WHERE bot_name IS NOT NULL;
+
+ALTER TABLE users
+    ALTER COLUMN telegram_id SET NOT NULL,
+    DROP COLUMN bot_name;
@@ -0,0 +1,12 @@
+-- Bot accounts are named by bot_name; they used to borrow telegram_id as "bot:<name>"
+ALTER TABLE users
+    ADD COLUMN bot_name TEXT UNIQUE,
+    ALTER COLUMN telegram_id DROP NOT NULL;
+
+UPDATE users
+SET bot_name = substr(telegram_id, 5), telegram_id = NULL
+WHERE is_bot AND telegram_id LIKE 'bot:%';
+
+ALTER TABLE users
+    ADD CONSTRAINT users_bot_name CHECK (is_bot = (bot_name IS NOT NULL)),
+    ADD CONSTRAINT users_telegram_id CHECK (is_bot OR telegram_id IS NOT NULL);
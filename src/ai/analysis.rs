@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::ai::solver::evaluate_line;
+use crate::utils::schemas::{Card, DealRecord, GameState, PlayerPosition};
+
+/// Point loss from which a play counts as a mistake.
+const MISTAKE_POINTS: u32 = 4;
+/// Point loss from which a play counts as a blunder.
+const BLUNDER_POINTS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Best,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Verdict {
+    fn from_cost(cost: u32) -> Self {
+        match cost {
+            0 => Verdict::Best,
+            c if c >= BLUNDER_POINTS => Verdict::Blunder,
+            c if c >= MISTAKE_POINTS => Verdict::Mistake,
+            _ => Verdict::Inaccuracy,
+        }
+    }
+}
+
+/// One card of the game next to the best card the seat had.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayReview {
+    pub deal: usize,
+    pub trick: usize,
+    pub position: PlayerPosition,
+    pub card: Card,
+    pub best_card: Card,
+    /// Points the player's team takes from the rest of the deal after the
+    /// played and the best card, with all hands open and perfect play.
+    pub points: u32,
+    pub best_points: u32,
+    pub cost: u32,
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameAnalysis {
+    pub plays: Vec<PlayReview>,
+    /// Points given away by each seat over the whole game.
+    pub total_cost: HashMap<PlayerPosition, u32>,
+}
+
+/// Replays a recorded deal and reviews every card by double-dummy search.
+pub fn analyze_deal(index: usize, deal: &DealRecord) -> Result<Vec<PlayReview>, &'static str> {
+    let Some((leader, _)) = deal.plays.first() else {
+        return Ok(vec![]);
+    };
+    let mut start = GameState::new(deal.trump);
+    start.hands = deal.hands.clone();
+    start.dealt_hands = deal.hands.clone();
+    start.current_turn = *leader;
+
+    let line = evaluate_line(&start, &deal.plays)?;
+    let mut reviews = vec![];
+    for (i, ((position, card), moves)) in deal.plays.iter().zip(line).enumerate() {
+        let points = moves.iter().find(|(c, _)| c == card).map(|(_, p)| *p).ok_or("Card was not legal")?;
+        let (best_card, best_points) = moves.iter()
+            .max_by_key(|(_, p)| *p)
+            .copied()
+            .ok_or("No legal cards")?;
+        let cost = best_points - points;
+        reviews.push(PlayReview {
+            deal: index,
+            trick: i / 4,
+            position: *position,
+            card: *card,
+            best_card: if cost == 0 { *card } else { best_card },
+            points,
+            best_points,
+            cost,
+            verdict: Verdict::from_cost(cost),
+        });
+    }
+    Ok(reviews)
+}
+
+/// Reviews every deal of a game. CPU heavy, run it off the async runtime.
+pub fn analyze_game(deals: &[DealRecord]) -> Result<GameAnalysis, &'static str> {
+    let mut plays = vec![];
+    for (i, deal) in deals.iter().enumerate() {
+        plays.extend(analyze_deal(i, deal)?);
+    }
+    let mut total_cost = HashMap::new();
+    for review in &plays {
+        *total_cost.entry(review.position).or_insert(0) += review.cost;
+    }
+    Ok(GameAnalysis { plays, total_cost })
+}
@@ -0,0 +1,324 @@
+//! Bots written in any language, run as a child process per seat.
+//!
+//! The protocol is line based JSON over the bot's stdin/stdout and mirrors
+//! the WebSocket API: the bot receives the same `WSEvent` objects a client
+//! gets (`game_start`, `trump_updated`, `your_hand`, `card_played`,
+//! `trick_won`, `your_turn`, `game_close`) and answers every `your_turn`
+//! with a `playcard` message, e.g. `{"op":"playcard","rank":"10","suit":"h"}`.
+//! Anything else the bot prints is ignored. A bot that is late, crashes or
+//! plays an illegal card gets a card picked for it; after too many strikes
+//! it is stopped and the seat is played by the heuristic bot.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use once_cell::sync::OnceCell;
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::ai::heuristic::HeuristicBot;
+use crate::ai::{PlayerView, Strategy};
+use crate::utils::schemas::{trick_winner, Card, SubOrUnsub, WSCardPlayed, WSEvent, WSIncomingMessage, WSTrickWon, WSYourHand, WSYourTurn};
+
+/// Time a bot gets per card unless `EXTERNAL_BOT_TIMEOUT_MS` says otherwise.
+pub const DEFAULT_MOVE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Late or illegal answers tolerated before the bot is replaced.
+const MAX_STRIKES: u32 = 3;
+
+/// An external bot the server operator allowed to be seated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExternalBotSpec {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+    pub move_timeout: Duration,
+}
+
+/// Registered external bots, set once at startup.
+static EXTERNAL_BOTS: OnceCell<Vec<ExternalBotSpec>> = OnceCell::new();
+
+/// Parses `name=program arg1 arg2;other=program2`, skipping malformed entries.
+pub fn parse_external_bots(config: &str, move_timeout: Duration) -> Vec<ExternalBotSpec> {
+    config
+        .split(';')
+        .filter_map(|entry| {
+            let (name, command) = entry.split_once('=')?;
+            let mut parts = command.split_whitespace().map(str::to_string);
+            Some(ExternalBotSpec {
+                name: name.trim().to_string(),
+                program: parts.next()?,
+                args: parts.collect(),
+                move_timeout,
+            })
+        })
+        .filter(|spec| !spec.name.is_empty())
+        .collect()
+}
+
+/// Makes the bots available to `BotKind::External`. Only the first call counts.
+pub fn register_external_bots(specs: Vec<ExternalBotSpec>) {
+    if EXTERNAL_BOTS.set(specs).is_err() {
+        warn!("External bots are already registered");
+    }
+}
+
+pub fn find_external_bot(name: &str) -> Option<&'static ExternalBotSpec> {
+    EXTERNAL_BOTS.get()?.iter().find(|spec| spec.name == name)
+}
+
+#[derive(Debug)]
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+/// Strategy that forwards the game to an external process.
+#[derive(Debug)]
+pub struct ExternalBot {
+    spec: &'static ExternalBotSpec,
+    session_id: String,
+    process: Option<Process>,
+    strikes: u32,
+    /// Plays of the current deal already sent to the bot.
+    sent_plays: usize,
+    started: bool,
+    fallback: HeuristicBot,
+}
+
+impl ExternalBot {
+    pub fn new(spec: &'static ExternalBotSpec) -> Self {
+        let process = match spawn(spec) {
+            Ok(process) => Some(process),
+            Err(e) => {
+                warn!("External bot {}: failed to start {}: {e}", spec.name, spec.program);
+                None
+            }
+        };
+        Self {
+            spec,
+            session_id: Uuid::new_v4().to_string(),
+            process,
+            strikes: 0,
+            sent_plays: 0,
+            started: false,
+            fallback: HeuristicBot,
+        }
+    }
+
+    fn send(&mut self, event: &WSEvent) -> std::io::Result<()> {
+        let Some(process) = self.process.as_mut() else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(event).expect("events always serialize");
+        writeln!(process.stdin, "{line}")?;
+        process.stdin.flush()
+    }
+
+    /// Events that bring the bot from what it knows up to `view`.
+    fn catch_up(&mut self, view: &PlayerView) -> Vec<WSEvent> {
+        let mut events = vec![];
+        if !self.started {
+            self.started = true;
+            events.push(WSEvent::GameStart { room_id: self.session_id.clone(), position: view.position });
+        }
+        if view.deal_plays.len() < self.sent_plays || (self.sent_plays == 0 && view.deal_plays.len() < 4) {
+            // Новая сдача: козырь и рука на начало сдачи
+            let mut dealt = view.hand.clone();
+            dealt.extend(view.deal_plays.iter().filter(|(p, _)| *p == view.position).map(|(_, c)| *c));
+            events.push(WSEvent::TrumpUpdated { trump: view.trump });
+            events.push(WSEvent::YourHand(WSYourHand { cards: dealt }));
+            self.sent_plays = 0;
+        }
+        for i in self.sent_plays..view.deal_plays.len() {
+            let (position, card) = view.deal_plays[i];
+            events.push(WSEvent::CardPlayed(WSCardPlayed { position, card }));
+            if i % 4 == 3 {
+                let trick = &view.deal_plays[i - 3..=i];
+                let winner = trick_winner(trick, view.trump).expect("trick is full");
+                events.push(WSEvent::TrickWon(WSTrickWon { position: winner }));
+            }
+        }
+        self.sent_plays = view.deal_plays.len();
+        events.push(WSEvent::YourHand(WSYourHand { cards: view.hand.clone() }));
+        events.push(WSEvent::YourTurn(WSYourTurn));
+        events
+    }
+
+    /// Waits for the bot's `playcard` until the move deadline.
+    fn read_move(&mut self) -> Result<Card, String> {
+        let timeout = self.spec.move_timeout;
+        let process = self.process.as_mut().ok_or("bot is not running")?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let line = match process.lines.recv_timeout(left) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Err(format!("no move within {timeout:?}")),
+                Err(RecvTimeoutError::Disconnected) => return Err("bot exited".to_string()),
+            };
+            if let Ok(WSIncomingMessage::Manage(SubOrUnsub::PlayCard(msg))) = serde_json::from_str(&line) {
+                return msg.card().map_err(str::to_string);
+            }
+        }
+    }
+
+    fn strike(&mut self, reason: &str) {
+        self.strikes += 1;
+        warn!("External bot {}: {reason} (strike {}/{MAX_STRIKES})", self.spec.name, self.strikes);
+        let _ = self.send(&WSEvent::Error { detail: reason.to_string() });
+        if self.strikes >= MAX_STRIKES {
+            warn!("External bot {}: replaced by the heuristic bot", self.spec.name);
+            self.stop("Too many strikes");
+        }
+    }
+
+    fn stop(&mut self, reason: &str) {
+        let _ = self.send(&WSEvent::GameClose { reason: reason.to_string() });
+        if let Some(mut process) = self.process.take() {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+    }
+}
+
+fn spawn(spec: &ExternalBotSpec) -> std::io::Result<Process> {
+    let mut child = Command::new(&spec.program)
+        .args(&spec.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+
+    // Чтение в отдельном потоке, чтобы ожидание хода можно было ограничить по времени
+    let (tx, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else { break };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    info!("External bot {} started, pid {}", spec.name, child.id());
+    Ok(Process { child, stdin, lines })
+}
+
+impl Strategy for ExternalBot {
+    fn name(&self) -> String {
+        format!("external-{}", self.spec.name)
+    }
+
+    fn choose_card(&mut self, view: &PlayerView) -> Card {
+        if self.process.is_none() {
+            return self.fallback.choose_card(view);
+        }
+
+        for event in self.catch_up(view) {
+            if self.send(&event).is_err() {
+                self.strike("bot closed its input");
+                return self.fallback.choose_card(view);
+            }
+        }
+
+        match self.read_move() {
+            Ok(card) if view.legal.contains(&card) => card,
+            Ok(card) => {
+                self.strike(&format!("illegal card {card:?}"));
+                self.fallback.choose_card(view)
+            }
+            Err(reason) => {
+                self.strike(&reason);
+                self.fallback.choose_card(view)
+            }
+        }
+    }
+}
+
+impl Drop for ExternalBot {
+    fn drop(&mut self) {
+        self.stop("GameOver");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::utils::schemas::{PlayerPosition, Rank, Suit};
+
+    const ACE_OF_HEARTS: Card = Card { suit: Suit::Hearts, rank: Rank::Ace };
+    const SEVEN_OF_SPADES: Card = Card { suit: Suit::Spades, rank: Rank::Seven };
+
+    fn spec(script: &str) -> &'static ExternalBotSpec {
+        Box::leak(Box::new(ExternalBotSpec {
+            name: "test".to_string(),
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            move_timeout: Duration::from_millis(200),
+        }))
+    }
+
+    /// West holding A♥ and 7♠; with `spade_led` North has led a spade.
+    fn view(spade_led: bool) -> PlayerView {
+        let trick = if spade_led {
+            vec![(PlayerPosition::North, Card { suit: Suit::Spades, rank: Rank::King })]
+        } else {
+            vec![]
+        };
+        PlayerView {
+            position: PlayerPosition::West,
+            hand: vec![ACE_OF_HEARTS, SEVEN_OF_SPADES],
+            legal: if spade_led { vec![SEVEN_OF_SPADES] } else { vec![ACE_OF_HEARTS, SEVEN_OF_SPADES] },
+            trump: Suit::Clubs,
+            current_trick: trick.clone(),
+            deal_plays: trick,
+            team_scores: HashMap::from([(1, 0), (2, 0)]),
+        }
+    }
+
+    #[test]
+    fn parses_bot_list() {
+        let specs = parse_external_bots("a=./bot --fast; =nameless;b=python3 b.py;broken", DEFAULT_MOVE_TIMEOUT);
+        assert_eq!(specs.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(specs[0].program, "./bot");
+        assert_eq!(specs[0].args, ["--fast"]);
+    }
+
+    #[test]
+    fn illegal_moves_get_the_bot_replaced() {
+        // Бот всегда ходит тузом червей
+        let mut bot = ExternalBot::new(spec(
+            r#"while read line; do case "$line" in *your_turn*) echo '{"op":"playcard","rank":"a","suit":"h"}';; esac; done"#,
+        ));
+        assert_eq!(bot.choose_card(&view(false)), ACE_OF_HEARTS);
+        assert_eq!(bot.strikes, 0);
+
+        for strike in 1..=MAX_STRIKES {
+            assert_eq!(bot.choose_card(&view(true)), SEVEN_OF_SPADES);
+            assert_eq!(bot.strikes, strike);
+        }
+        assert!(bot.process.is_none());
+        assert!(view(false).legal.contains(&bot.choose_card(&view(false))));
+        assert_eq!(bot.strikes, MAX_STRIKES);
+    }
+
+    #[test]
+    fn silent_or_missing_bots_are_played_for() {
+        let mut silent = ExternalBot::new(spec("cat > /dev/null"));
+        assert_eq!(silent.choose_card(&view(true)), SEVEN_OF_SPADES);
+        assert_eq!(silent.strikes, 1);
+
+        let mut missing = ExternalBot::new(Box::leak(Box::new(ExternalBotSpec {
+            name: "missing".to_string(),
+            program: "/nonexistent/bot".to_string(),
+            args: vec![],
+            move_timeout: DEFAULT_MOVE_TIMEOUT,
+        })));
+        assert!(missing.process.is_none());
+        assert_eq!(missing.choose_card(&view(true)), SEVEN_OF_SPADES);
+        assert_eq!(missing.strikes, 0);
+    }
+}
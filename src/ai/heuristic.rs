@@ -0,0 +1,148 @@
+use crate::ai::{PlayerView, Strategy};
+use crate::utils::schemas::{card_power, is_trump, trick_winner, Card, Rank, Suit};
+
+/// Trick value that justifies spending a jack to win it.
+const JACK_SPEND_POINTS: u32 = 10;
+/// Trick value worth contesting with a winner that is not guaranteed.
+const CONTEST_POINTS: u32 = 10;
+/// Trumps we want in hand before leading a sure trump to draw the rest.
+const DRAW_TRUMPS_MIN: usize = 3;
+
+/// Rule-based player: follows the partner, trumps when it pays off, keeps
+/// jacks for valuable tricks and counts which cards are still out.
+#[derive(Debug, Default)]
+pub struct HeuristicBot;
+
+impl Strategy for HeuristicBot {
+    fn name(&self) -> String {
+        "heuristic".to_string()
+    }
+
+    fn choose_card(&mut self, view: &PlayerView) -> Card {
+        suggest(view).0
+    }
+}
+
+/// The card the heuristic player would pick, with a short reason a new
+/// player can follow.
+pub fn suggest(view: &PlayerView) -> (Card, &'static str) {
+    if view.current_trick.is_empty() {
+        lead(view)
+    } else {
+        follow(view)
+    }
+}
+
+fn points(card: Card) -> u32 {
+    card.points() as u32
+}
+
+/// No card still out can beat `card` in a trick led with `lead_suit`.
+fn is_sure_winner(view: &PlayerView, card: Card, lead_suit: Suit) -> bool {
+    let power = card_power(card, lead_suit, view.trump);
+    view.unseen().iter().all(|c| card_power(*c, lead_suit, view.trump) < power)
+}
+
+/// Cheapest card to throw away: few points, keep trumps, keep strength.
+fn lowest(view: &PlayerView) -> Card {
+    let trump = view.trump;
+    *view.legal.iter()
+        .min_by_key(|c| (points(**c), is_trump(**c, trump), card_power(**c, c.suit, trump)))
+        .expect("a seat on move always has a legal card")
+}
+
+fn lead(view: &PlayerView) -> (Card, &'static str) {
+    let trump = view.trump;
+
+    // Cash a side-suit card nobody can beat.
+    let sure_side = view.legal.iter()
+        .filter(|c| !is_trump(**c, trump) && is_sure_winner(view, **c, c.suit))
+        .max_by_key(|c| points(**c));
+    if let Some(card) = sure_side {
+        return (*card, "nobody can beat this card, cash it");
+    }
+
+    // With trump length, pull the opponents' trumps with a top trump.
+    let trumps: Vec<Card> = view.hand.iter().copied().filter(|c| is_trump(*c, trump)).collect();
+    let trumps_out = view.unseen().iter().any(|c| is_trump(*c, trump));
+    if trumps.len() >= DRAW_TRUMPS_MIN && trumps_out {
+        if let Some(card) = trumps.iter().find(|c| is_sure_winner(view, **c, c.suit)) {
+            return (*card, "you hold the top trump, draw the opponents' trumps");
+        }
+    }
+
+    // Otherwise probe with a low card of our longest side suit.
+    let side: Vec<Card> = view.legal.iter().copied().filter(|c| !is_trump(*c, trump)).collect();
+    let longest = side.iter()
+        .max_by_key(|c| side.iter().filter(|o| o.suit == c.suit).count())
+        .map(|c| c.suit);
+    if let Some(suit) = longest {
+        let card = side.iter()
+            .filter(|c| c.suit == suit)
+            .min_by_key(|c| (points(**c), c.rank))
+            .expect("longest suit is not empty");
+        return (*card, "lead a low card of your longest suit");
+    }
+
+    (lowest(view), "only trumps left, lead the cheapest one")
+}
+
+fn follow(view: &PlayerView) -> (Card, &'static str) {
+    let trump = view.trump;
+    let lead_suit = view.current_trick[0].1.suit;
+    let winner = trick_winner(&view.current_trick, trump).expect("trick is not empty");
+    let winning_card = view.current_trick.iter().find(|(p, _)| *p == winner).expect("winner played").1;
+    let winning_power = card_power(winning_card, lead_suit, trump);
+    let last_to_play = view.current_trick.len() == 3;
+    let trick_points: u32 = view.current_trick.iter().map(|(_, c)| points(*c)).sum();
+
+    if winner == view.position.next().next() {
+        // Partner holds the trick: load points on it if nobody can take it.
+        if last_to_play || is_sure_winner(view, winning_card, lead_suit) {
+            let smear = view.legal.iter()
+                .filter(|c| c.rank != Rank::Jack)
+                .max_by_key(|c| (!is_trump(**c, trump), points(**c)));
+            if let Some(card) = smear {
+                return (*card, "partner is winning the trick, add points to it");
+            }
+        }
+        return (lowest(view), "partner is winning the trick, keep your strong cards");
+    }
+
+    let winners: Vec<Card> = view.legal.iter().copied()
+        .filter(|c| card_power(*c, lead_suit, trump) > winning_power)
+        .collect();
+    if winners.is_empty() {
+        return (lowest(view), "you can not win this trick, give up the cheapest card");
+    }
+
+    let plain: Vec<Card> = winners.iter().copied().filter(|c| c.rank != Rank::Jack).collect();
+    if !plain.is_empty() {
+        if last_to_play {
+            // Nobody plays after us: take it with the most valuable card that wins.
+            let card = plain.iter()
+                .max_by_key(|c| (!is_trump(**c, trump), points(**c)))
+                .expect("plain winners are not empty");
+            return (*card, "you play last, take the trick with your most valuable winner");
+        }
+        if let Some(card) = plain.iter().filter(|c| is_sure_winner(view, **c, lead_suit)).min_by_key(|c| card_power(**c, lead_suit, trump)) {
+            return (*card, "the cheapest card that surely wins the trick");
+        }
+        if trick_points >= CONTEST_POINTS {
+            let card = plain.iter()
+                .max_by_key(|c| card_power(**c, lead_suit, trump))
+                .expect("plain winners are not empty");
+            return (*card, "the trick is worth fighting for");
+        }
+        return (lowest(view), "the trick is cheap, do not spend a high card on it");
+    }
+
+    // Only jacks win: spend the smallest one, and only on a valuable trick.
+    if trick_points >= JACK_SPEND_POINTS {
+        let card = winners.iter()
+            .min_by_key(|c| card_power(**c, lead_suit, trump))
+            .expect("winners are not empty");
+        return (*card, "only a jack wins and the trick is worth it; jacks rank clubs, diamonds, hearts, spades");
+    }
+    (lowest(view), "save your jacks for a valuable trick")
+}
@@ -0,0 +1,159 @@
+pub mod analysis;
+pub mod external;
+pub mod heuristic;
+pub mod montecarlo;
+pub mod random;
+pub mod solver;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+use crate::utils::schemas::{full_deck, Card, GameState, PlayerPosition, Suit};
+use self::external::{find_external_bot, ExternalBot, ExternalBotSpec};
+use self::heuristic::HeuristicBot;
+use self::montecarlo::MonteCarloBot;
+use self::random::RandomBot;
+pub use self::montecarlo::Difficulty;
+
+/// What a single seat knows about the deal in progress.
+#[derive(Debug, Clone)]
+pub struct PlayerView {
+    pub position: PlayerPosition,
+    pub hand: Vec<Card>,
+    pub legal: Vec<Card>,
+    pub trump: Suit,
+    pub current_trick: Vec<(PlayerPosition, Card)>,
+    /// Every card played in the deal so far, including the current trick.
+    pub deal_plays: Vec<(PlayerPosition, Card)>,
+    pub team_scores: HashMap<u8, u32>,
+}
+
+impl PlayerView {
+    pub fn from_state(state: &GameState, position: PlayerPosition) -> Self {
+        Self {
+            position,
+            hand: state.hands.get(&position).cloned().unwrap_or_default(),
+            legal: state.legal_cards(position),
+            trump: state.trump,
+            current_trick: state.current_trick.clone(),
+            deal_plays: state.deal_plays.clone(),
+            team_scores: state.team_scores.clone(),
+        }
+    }
+
+    /// Cards neither in our hand nor played yet, i.e. held by the other three.
+    pub fn unseen(&self) -> Vec<Card> {
+        full_deck()
+            .into_iter()
+            .filter(|c| !self.hand.contains(c) && !self.deal_plays.iter().any(|(_, p)| p == c))
+            .collect()
+    }
+}
+
+/// Something that can pick a card for a seat.
+pub trait Strategy: Send + std::fmt::Debug {
+    fn name(&self) -> String;
+
+    /// Must return one of `view.legal`.
+    fn choose_card(&mut self, view: &PlayerView) -> Card;
+}
+
+/// Bot strategies the server knows how to create.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotKind {
+    Random,
+    Heuristic,
+    MonteCarlo(Difficulty),
+    /// A process registered with `register_external_bots`.
+    External(&'static ExternalBotSpec),
+}
+
+impl BotKind {
+    /// Same as the `Strategy::name` of what `create` returns.
+    pub fn name(&self) -> String {
+        match self {
+            BotKind::Random => "random".to_string(),
+            BotKind::Heuristic => "heuristic".to_string(),
+            BotKind::MonteCarlo(difficulty) => format!("montecarlo-{}", difficulty.name()),
+            BotKind::External(spec) => format!("external-{}", spec.name),
+        }
+    }
+
+    pub fn create(&self) -> Box<dyn Strategy> {
+        match self {
+            BotKind::Random => Box::new(RandomBot::new()),
+            BotKind::Heuristic => Box::new(HeuristicBot),
+            BotKind::MonteCarlo(difficulty) => Box::new(MonteCarloBot::new(*difficulty)),
+            BotKind::External(spec) => Box::new(ExternalBot::new(spec)),
+        }
+    }
+
+    /// Same as `create`, but any randomness of the strategy is seeded.
+    pub fn create_seeded(&self, seed: u64) -> Box<dyn Strategy> {
+        match self {
+            BotKind::Random => Box::new(RandomBot::with_seed(seed)),
+            BotKind::Heuristic => Box::new(HeuristicBot),
+            BotKind::MonteCarlo(difficulty) => Box::new(MonteCarloBot::with_seed(*difficulty, seed)),
+            BotKind::External(spec) => Box::new(ExternalBot::new(spec)),
+        }
+    }
+}
+
+impl std::str::FromStr for BotKind {
+    type Err = String;
+
+    /// Accepts the strategy names: `random`, `heuristic`, `montecarlo-easy`…
+    /// and `external:<name>` for registered external bots. Whatever
+    /// `BotKind::name` returns parses back to the same kind.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix("external:").or_else(|| s.strip_prefix("external-")) {
+            return find_external_bot(name).map(BotKind::External).ok_or(format!("unknown external bot {name}"));
+        }
+        match s {
+            "random" => Ok(BotKind::Random),
+            "heuristic" => Ok(BotKind::Heuristic),
+            "montecarlo-easy" => Ok(BotKind::MonteCarlo(Difficulty::Easy)),
+            "montecarlo-medium" | "montecarlo" => Ok(BotKind::MonteCarlo(Difficulty::Medium)),
+            "montecarlo-hard" => Ok(BotKind::MonteCarlo(Difficulty::Hard)),
+            _ => Err(format!("unknown strategy {s}")),
+        }
+    }
+}
+
+/// A strategy sitting at a table. Thinking is synchronous, so rooms run it
+/// on the blocking pool.
+#[derive(Debug, Clone)]
+pub struct BotPlayer {
+    /// Stands in for a player id in lineups and series scores.
+    pub id: String,
+    pub name: String,
+    pub kind: BotKind,
+    pub strategy: Arc<Mutex<Box<dyn Strategy>>>,
+}
+
+impl BotPlayer {
+    pub fn new(kind: BotKind) -> Self {
+        BotPlayer::with_id(kind, format!("bot-{}", Uuid::new_v4().simple()))
+    }
+
+    /// A bot keeping the id it had before, e.g. in a restored room.
+    pub fn with_id(kind: BotKind, id: String) -> Self {
+        let strategy = kind.create();
+        Self {
+            id,
+            name: strategy.name(),
+            kind,
+            strategy: Arc::new(Mutex::new(strategy)),
+        }
+    }
+
+    pub fn choose_card(&self, view: &PlayerView) -> Card {
+        let card = self.strategy.lock().expect("bot strategy poisoned").choose_card(view);
+        if view.legal.contains(&card) {
+            card
+        } else {
+            // Never let a faulty strategy stall the table.
+            view.legal[0]
+        }
+    }
+}
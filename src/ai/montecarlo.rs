@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::ai::heuristic::HeuristicBot;
+use crate::ai::{PlayerView, Strategy};
+use crate::utils::schemas::{Card, GameState, PlayerPosition, Suit};
+
+/// Attempts to deal the hidden cards around the known voids before giving
+/// up on them for this sample.
+const DEAL_ATTEMPTS: usize = 20;
+
+/// How much thinking a search bot may do per card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    /// Maximum sampled deals per move.
+    pub fn samples(&self) -> usize {
+        match self {
+            Difficulty::Easy => 16,
+            Difficulty::Medium => 96,
+            Difficulty::Hard => 400,
+        }
+    }
+
+    /// Wall-clock limit per move, whichever of the two runs out first.
+    pub fn time_budget(&self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(50),
+            Difficulty::Medium => Duration::from_millis(300),
+            Difficulty::Hard => Duration::from_millis(1200),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+/// Determinized Monte Carlo player: deals the unseen cards to the other
+/// seats in ways consistent with what was played, finishes the deal with
+/// the heuristic player for every candidate card and keeps the card with
+/// the best average point result.
+#[derive(Debug)]
+pub struct MonteCarloBot {
+    pub difficulty: Difficulty,
+    rng: StdRng,
+}
+
+impl MonteCarloBot {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self { difficulty, rng: StdRng::from_entropy() }
+    }
+
+    pub fn with_seed(difficulty: Difficulty, seed: u64) -> Self {
+        Self { difficulty, rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Strategy for MonteCarloBot {
+    fn name(&self) -> String {
+        format!("montecarlo-{}", self.difficulty.name())
+    }
+
+    fn choose_card(&mut self, view: &PlayerView) -> Card {
+        if view.legal.len() == 1 {
+            return view.legal[0];
+        }
+
+        let started = Instant::now();
+        let mut totals = vec![0u64; view.legal.len()];
+        let mut samples = 0;
+        while samples < self.difficulty.samples() && started.elapsed() < self.difficulty.time_budget() {
+            let hands = sample_hands(view, &mut self.rng);
+            for (i, card) in view.legal.iter().enumerate() {
+                totals[i] += rollout(view, &hands, *card) as u64;
+            }
+            samples += 1;
+        }
+
+        let best = totals.iter().enumerate().max_by_key(|(_, total)| **total).map(|(i, _)| i).unwrap_or(0);
+        view.legal[best]
+    }
+}
+
+/// Suits each seat has shown to be out of by not following the lead.
+pub fn known_voids(view: &PlayerView) -> HashMap<PlayerPosition, HashSet<Suit>> {
+    let mut voids: HashMap<PlayerPosition, HashSet<Suit>> = HashMap::new();
+    for trick in view.deal_plays.chunks(4) {
+        let lead_suit = trick[0].1.suit;
+        for (pos, card) in &trick[1..] {
+            if card.suit != lead_suit {
+                voids.entry(*pos).or_default().insert(lead_suit);
+            }
+        }
+    }
+    voids
+}
+
+/// One guess at the full deal: our own hand plus the unseen cards spread
+/// over the other seats, respecting their hand sizes and known voids.
+pub fn sample_hands(view: &PlayerView, rng: &mut impl Rng) -> HashMap<PlayerPosition, Vec<Card>> {
+    let voids = known_voids(view);
+    let others = [view.position.next(), view.position.next().next(), view.position.next().next().next()];
+    let sizes: Vec<usize> = others.iter()
+        .map(|pos| view.hand.len() - hand_size_offset(view, *pos))
+        .collect();
+    let mut unseen = view.unseen();
+
+    for attempt in 0..=DEAL_ATTEMPTS {
+        let respect_voids = attempt < DEAL_ATTEMPTS;
+        unseen.shuffle(rng);
+        // Самые ограниченные карты раздаём первыми
+        unseen.sort_by_key(|c| others.iter().filter(|p| !is_void(&voids, **p, c.suit)).count());
+
+        let mut hands: Vec<Vec<Card>> = vec![vec![]; 3];
+        let mut dealt = true;
+        for card in &unseen {
+            let open: Vec<usize> = (0..3)
+                .filter(|i| hands[*i].len() < sizes[*i])
+                .filter(|i| !respect_voids || !is_void(&voids, others[*i], card.suit))
+                .collect();
+            match open.choose(rng) {
+                Some(i) => hands[*i].push(*card),
+                None => {
+                    dealt = false;
+                    break;
+                }
+            }
+        }
+        if dealt {
+            let mut result: HashMap<PlayerPosition, Vec<Card>> = others.into_iter().zip(hands).collect();
+            result.insert(view.position, view.hand.clone());
+            return result;
+        }
+    }
+    unreachable!("ignoring voids always deals every unseen card")
+}
+
+fn is_void(voids: &HashMap<PlayerPosition, HashSet<Suit>>, pos: PlayerPosition, suit: Suit) -> bool {
+    voids.get(&pos).is_some_and(|v| v.contains(&suit))
+}
+
+/// How many cards `pos` holds fewer than we do: seats that already played
+/// to the current trick are one card shorter.
+fn hand_size_offset(view: &PlayerView, pos: PlayerPosition) -> usize {
+    let played_ours = view.current_trick.iter().any(|(p, _)| *p == view.position);
+    let played_theirs = view.current_trick.iter().any(|(p, _)| *p == pos);
+    (played_theirs && !played_ours) as usize
+}
+
+/// Plays `card` in a sampled deal and finishes the deal with the heuristic
+/// player on every seat. Returns the points our team collects from here on.
+pub fn rollout(view: &PlayerView, hands: &HashMap<PlayerPosition, Vec<Card>>, card: Card) -> u32 {
+    let mut state = GameState {
+        hands: hands.clone(),
+        trump: view.trump,
+        current_trick: view.current_trick.clone(),
+        deal_plays: view.deal_plays.clone(),
+        dealt_hands: HashMap::new(),
+        taken: HashMap::from([(1, vec![]), (2, vec![])]),
+        last_trick: vec![],
+        team_scores: HashMap::from([(1, 0), (2, 0)]),
+        team_eye: HashMap::new(),
+        current_turn: view.position,
+        is_first_round: false,
+    };
+
+    let mut next = Some(card);
+    let mut bot = HeuristicBot;
+    while state.hands.values().any(|h| !h.is_empty()) {
+        let pos = state.current_turn;
+        let card = next.take().unwrap_or_else(|| bot.choose_card(&PlayerView::from_state(&state, pos)));
+        if state.play_card(pos, card).is_err() {
+            break;
+        }
+        state.resolve_trick();
+    }
+    state.team_scores.get(&view.position.team()).copied().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::schemas::{full_deck, Rank};
+
+    fn suit(suit: Suit) -> Vec<Card> {
+        full_deck().into_iter().filter(|c| c.suit == suit).collect()
+    }
+
+    fn card(rank: Rank, suit: Suit) -> Card {
+        Card { suit, rank }
+    }
+
+    /// Every seat holds one suit; only North follows the spade lead.
+    fn one_suit_each() -> GameState {
+        let hands = HashMap::from([
+            (PlayerPosition::North, suit(Suit::Spades)),
+            (PlayerPosition::East, suit(Suit::Clubs)),
+            (PlayerPosition::South, suit(Suit::Hearts)),
+            (PlayerPosition::West, suit(Suit::Diamonds)),
+        ]);
+        let mut state = GameState::new(Suit::Hearts);
+        state.dealt_hands = hands.clone();
+        state.hands = hands;
+        for (pos, card) in [
+            (PlayerPosition::North, card(Rank::Seven, Suit::Spades)),
+            (PlayerPosition::East, card(Rank::Seven, Suit::Clubs)),
+            (PlayerPosition::South, card(Rank::Seven, Suit::Hearts)),
+            (PlayerPosition::West, card(Rank::Seven, Suit::Diamonds)),
+        ] {
+            state.play_card(pos, card).unwrap();
+        }
+        assert_eq!(state.resolve_trick(), Some(PlayerPosition::South));
+        state
+    }
+
+    #[test]
+    fn seats_that_did_not_follow_are_void() {
+        let view = PlayerView::from_state(&one_suit_each(), PlayerPosition::South);
+        let voids = known_voids(&view);
+        assert!(!voids.contains_key(&PlayerPosition::North));
+        for pos in [PlayerPosition::East, PlayerPosition::South, PlayerPosition::West] {
+            assert_eq!(voids[&pos], HashSet::from([Suit::Spades]));
+        }
+    }
+
+    #[test]
+    fn samples_keep_hidden_cards_away_from_void_seats() {
+        let mut state = one_suit_each();
+        state.play_card(PlayerPosition::South, card(Rank::Eight, Suit::Hearts)).unwrap();
+        // West ещё не ходил, South уже сыграл: у South на карту меньше
+        let view = PlayerView::from_state(&state, PlayerPosition::West);
+        let spades: HashSet<Card> = view.unseen().into_iter().filter(|c| c.suit == Suit::Spades).collect();
+        assert_eq!(spades.len(), 7);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..50 {
+            let hands = sample_hands(&view, &mut rng);
+            assert_eq!(hands[&PlayerPosition::West], view.hand);
+            assert_eq!(hands[&PlayerPosition::North].iter().copied().collect::<HashSet<_>>(), spades);
+            assert_eq!(hands[&PlayerPosition::East].len(), 7);
+            assert_eq!(hands[&PlayerPosition::South].len(), 6);
+            let mut dealt: Vec<Card> = hands.iter()
+                .filter(|(pos, _)| **pos != PlayerPosition::West)
+                .flat_map(|(_, hand)| hand.iter().copied())
+                .collect();
+            let mut unseen = view.unseen();
+            dealt.sort_by_key(|c| (c.suit as u8, c.rank as u8));
+            unseen.sort_by_key(|c| (c.suit as u8, c.rank as u8));
+            assert_eq!(dealt, unseen);
+        }
+    }
+}
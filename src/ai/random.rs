@@ -0,0 +1,37 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use crate::ai::{PlayerView, Strategy};
+use crate::utils::schemas::Card;
+
+/// Plays any legal card. Baseline opponent for measuring other bots.
+#[derive(Debug)]
+pub struct RandomBot {
+    rng: StdRng,
+}
+
+impl RandomBot {
+    pub fn new() -> Self {
+        Self { rng: StdRng::from_entropy() }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl Default for RandomBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Strategy for RandomBot {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn choose_card(&mut self, view: &PlayerView) -> Card {
+        *view.legal.choose(&mut self.rng).expect("a seat on move always has a legal card")
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use crate::utils::schemas::{full_deck, jack_priority, Card, GameState, PlayerPosition, Rank, Suit};
+
+const SEATS: [PlayerPosition; 4] = [PlayerPosition::North, PlayerPosition::East, PlayerPosition::South, PlayerPosition::West];
+const SUITS: [Suit; 4] = [Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
+
+/// A set of cards as a 32-bit mask, one bit per card of the deck.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CardSet(pub u32);
+
+impl CardSet {
+    pub fn index(card: Card) -> u32 {
+        let suit = SUITS.iter().position(|s| *s == card.suit).expect("suit is listed") as u32;
+        suit * 8 + card.rank as u32
+    }
+
+    pub fn card(index: u32) -> Card {
+        full_deck()[index as usize]
+    }
+
+    pub fn suit_mask(suit: Suit) -> CardSet {
+        CardSet(0xFF << (CardSet::index(Card { suit, rank: Rank::Seven })))
+    }
+
+    pub fn from_cards(cards: &[Card]) -> Self {
+        CardSet(cards.iter().fold(0, |mask, c| mask | 1 << CardSet::index(*c)))
+    }
+
+    pub fn contains(&self, card: Card) -> bool {
+        self.0 & (1 << CardSet::index(card)) != 0
+    }
+
+    pub fn insert(&mut self, card: Card) {
+        self.0 |= 1 << CardSet::index(card);
+    }
+
+    pub fn remove(&mut self, card: Card) {
+        self.0 &= !(1 << CardSet::index(card));
+    }
+
+    pub fn len(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn cards(&self) -> Vec<Card> {
+        let mut cards = vec![];
+        let mut mask = self.0;
+        while mask != 0 {
+            let index = mask.trailing_zeros();
+            cards.push(CardSet::card(index));
+            mask &= mask - 1;
+        }
+        cards
+    }
+}
+
+/// Result of a double-dummy solve from the current point of the deal.
+#[derive(Debug, Clone)]
+pub struct DoubleDummy {
+    /// Card points each team takes from the cards still in play (current
+    /// trick included) when everybody plays perfectly.
+    pub team_points: HashMap<u8, u32>,
+    /// Best card for the seat on move.
+    pub best: Option<Card>,
+    /// Searched positions, for benchmarking.
+    pub nodes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bound {
+    lower: u32,
+    upper: u32,
+}
+
+/// Open-hand position in compact form: seats are indices into `SEATS`.
+#[derive(Debug, Clone)]
+struct Position {
+    hands: [CardSet; 4],
+    trick: Vec<(usize, Card)>,
+    turn: usize,
+}
+
+impl Position {
+    fn from_state(state: &GameState) -> Self {
+        let seat = |pos: PlayerPosition| SEATS.iter().position(|p| *p == pos).expect("seat is listed");
+        let mut hands = [CardSet::default(); 4];
+        for (i, pos) in SEATS.iter().enumerate() {
+            hands[i] = CardSet::from_cards(state.hands.get(pos).map(Vec::as_slice).unwrap_or(&[]));
+        }
+        Self {
+            hands,
+            trick: state.current_trick.iter().map(|(pos, card)| (seat(*pos), *card)).collect(),
+            turn: seat(state.current_turn),
+        }
+    }
+
+    /// Remaining cards plus the leader identify a trick-start position.
+    fn key(&self) -> (u128, usize) {
+        let key = self.hands.iter().enumerate().fold(0u128, |key, (i, h)| key | (h.0 as u128) << (32 * i));
+        (key, self.turn)
+    }
+
+    fn legal(&self) -> CardSet {
+        let hand = self.hands[self.turn];
+        let Some((_, lead)) = self.trick.first() else {
+            return hand;
+        };
+        let follow = CardSet(hand.0 & CardSet::suit_mask(lead.suit).0);
+        if follow.is_empty() { hand } else { follow }
+    }
+}
+
+fn team1(seat: usize) -> bool {
+    SEATS[seat].team() == 1
+}
+
+/// Ordering value of a card inside a trick, same order as `card_power`.
+fn power(card: Card, lead_suit: Suit, trump: Suit) -> u32 {
+    let rank = card.rank as u32;
+    if card.rank == Rank::Jack {
+        300 + jack_priority(card.suit) as u32
+    } else if card.suit == trump {
+        200 + rank
+    } else if card.suit == lead_suit {
+        100 + rank
+    } else {
+        rank
+    }
+}
+
+/// Alpha–beta search over open hands, scoring team 1's card points, with a
+/// transposition table of trick-start positions.
+struct Solver {
+    trump: Suit,
+    /// Card points by card index.
+    points: [u32; 32],
+    table: HashMap<(u128, usize), Bound>,
+    nodes: u64,
+}
+
+impl Solver {
+    fn new(trump: Suit) -> Self {
+        let mut points = [0; 32];
+        for card in full_deck() {
+            points[CardSet::index(card) as usize] = card.points() as u32;
+        }
+        Self { trump, points, table: HashMap::new(), nodes: 0 }
+    }
+
+    fn set_points(&self, set: CardSet) -> u32 {
+        let mut total = 0;
+        let mut mask = set.0;
+        while mask != 0 {
+            total += self.points[mask.trailing_zeros() as usize];
+            mask &= mask - 1;
+        }
+        total
+    }
+
+    fn remaining_points(&self, pos: &Position) -> u32 {
+        let in_hands: u32 = pos.hands.iter().map(|h| self.set_points(*h)).sum();
+        in_hands + pos.trick.iter().map(|(_, c)| c.points() as u32).sum::<u32>()
+    }
+
+    /// Candidate cards, likely best first so cut-offs come early.
+    fn ordered_moves(&self, pos: &Position) -> Vec<Card> {
+        let lead_suit = pos.trick.first().map(|(_, c)| c.suit);
+        let mut moves = pos.legal().cards();
+        moves.sort_by_key(|c| {
+            let strength = power(*c, lead_suit.unwrap_or(c.suit), self.trump);
+            std::cmp::Reverse((strength, c.points()))
+        });
+        moves
+    }
+
+    fn search(&mut self, pos: &mut Position, mut alpha: u32, mut beta: u32) -> u32 {
+        self.nodes += 1;
+        if pos.hands.iter().all(CardSet::is_empty) && pos.trick.is_empty() {
+            return 0;
+        }
+
+        let at_trick_start = pos.trick.is_empty();
+        if at_trick_start {
+            if let Some(bound) = self.table.get(&pos.key()) {
+                if bound.lower == bound.upper || bound.lower >= beta {
+                    return bound.lower;
+                }
+                if bound.upper <= alpha {
+                    return bound.upper;
+                }
+                alpha = alpha.max(bound.lower);
+                beta = beta.min(bound.upper);
+            }
+        }
+        let (alpha0, beta0) = (alpha, beta);
+
+        let maximizing = team1(pos.turn);
+        let mut best = if maximizing { 0 } else { u32::MAX };
+        for card in self.ordered_moves(pos) {
+            let value = self.play(pos, card, alpha, beta);
+            if maximizing {
+                best = best.max(value);
+                alpha = alpha.max(best);
+            } else {
+                best = best.min(value);
+                beta = beta.min(best);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if at_trick_start {
+            let entry = self.table.entry(pos.key()).or_insert(Bound { lower: 0, upper: u32::MAX });
+            if best <= alpha0 {
+                entry.upper = best;
+            } else if best >= beta0 {
+                entry.lower = best;
+            } else {
+                *entry = Bound { lower: best, upper: best };
+            }
+        }
+        best
+    }
+
+    /// Plays `card` for the seat on move, searches on and takes it back.
+    fn play(&mut self, pos: &mut Position, card: Card, alpha: u32, beta: u32) -> u32 {
+        let seat = pos.turn;
+        pos.hands[seat].remove(card);
+        pos.trick.push((seat, card));
+
+        let value = if pos.trick.len() == 4 {
+            let trick = std::mem::take(&mut pos.trick);
+            let lead_suit = trick[0].1.suit;
+            let winner = trick.iter().max_by_key(|(_, c)| power(*c, lead_suit, self.trump)).expect("trick is full").0;
+            let points: u32 = trick.iter().map(|(_, c)| c.points() as u32).sum();
+            let won = if team1(winner) { points } else { 0 };
+
+            let left = pos.hands.iter().map(|h| self.set_points(*h)).sum::<u32>();
+            // Исход уже ясен относительно окна — дальше не считаем
+            let value = if won >= beta {
+                won
+            } else if won + left <= alpha {
+                won + left
+            } else {
+                pos.turn = winner;
+                won + self.search(pos, alpha.saturating_sub(won), beta - won)
+            };
+            pos.trick = trick;
+            value
+        } else {
+            pos.turn = (seat + 1) % 4;
+            self.search(pos, alpha, beta)
+        };
+
+        pos.trick.pop();
+        pos.hands[seat].insert(card);
+        pos.turn = seat;
+        value
+    }
+}
+
+fn team_points(team1_points: u32, total: u32) -> HashMap<u8, u32> {
+    HashMap::from([(1, team1_points), (2, total - team1_points)])
+}
+
+/// Solves the rest of the deal with all hands open.
+pub fn solve(state: &GameState) -> DoubleDummy {
+    let mut solver = Solver::new(state.trump);
+    let mut pos = Position::from_state(state);
+    let total = solver.remaining_points(&pos);
+    let team1_points = solver.search(&mut pos, 0, total + 1);
+
+    let moves = evaluate_with(&mut solver, &mut pos);
+    let best = moves.iter().max_by_key(|(_, points)| *points).map(|(card, _)| *card);
+    DoubleDummy {
+        team_points: team_points(team1_points, total),
+        best,
+        nodes: solver.nodes,
+    }
+}
+
+/// Points the mover's team takes from the remaining cards after each of its
+/// legal cards, everybody playing perfectly afterwards.
+pub fn evaluate_moves(state: &GameState) -> Vec<(Card, u32)> {
+    let mut solver = Solver::new(state.trump);
+    let mut pos = Position::from_state(state);
+    evaluate_with(&mut solver, &mut pos)
+}
+
+fn evaluate_with(solver: &mut Solver, pos: &mut Position) -> Vec<(Card, u32)> {
+    let total = solver.remaining_points(pos);
+    let mover_team1 = team1(pos.turn);
+    let mut moves = vec![];
+    for card in pos.legal().cards() {
+        let team1_points = solver.play(pos, card, 0, total + 1);
+        moves.push((card, if mover_team1 { team1_points } else { total - team1_points }));
+    }
+    moves
+}
+
+/// Evaluates every position of a played line with one shared transposition
+/// table: entry `i` holds the values of the legal cards before play `i`.
+/// Later positions are subtrees of earlier ones, so most are table hits.
+pub fn evaluate_line(start: &GameState, plays: &[(PlayerPosition, Card)]) -> Result<Vec<Vec<(Card, u32)>>, &'static str> {
+    let mut solver = Solver::new(start.trump);
+    let mut state = start.clone();
+    let mut line = Vec::with_capacity(plays.len());
+    for (pos, card) in plays {
+        let mut position = Position::from_state(&state);
+        line.push(evaluate_with(&mut solver, &mut position));
+        state.play_card(*pos, *card)?;
+        state.resolve_trick();
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn card(rank: Rank, suit: Suit) -> Card {
+        Card { suit, rank }
+    }
+
+    fn ending(trump: Suit, hands: [Vec<Card>; 4]) -> GameState {
+        let mut state = GameState::new(trump);
+        state.hands = SEATS.into_iter().zip(hands).collect();
+        state
+    }
+
+    /// Plain minimax over `GameState`, no table and no pruning.
+    fn brute_force(state: &GameState) -> u32 {
+        let pos = state.current_turn;
+        let legal = state.legal_cards(pos);
+        if legal.is_empty() {
+            return 0;
+        }
+        let values = legal.into_iter().map(|card| {
+            let mut next = state.clone();
+            next.play_card(pos, card).unwrap();
+            let trick: u32 = next.current_trick.iter().map(|(_, c)| c.points() as u32).sum();
+            let won = match next.resolve_trick() {
+                Some(winner) if winner.team() == 1 => trick,
+                _ => 0,
+            };
+            won + brute_force(&next)
+        });
+        if pos.team() == 1 { values.max().unwrap() } else { values.min().unwrap() }
+    }
+
+    #[test]
+    fn last_trick_goes_to_the_highest_card() {
+        let state = ending(Suit::Clubs, [
+            vec![card(Rank::Ace, Suit::Hearts)],
+            vec![card(Rank::Seven, Suit::Hearts)],
+            vec![card(Rank::Ten, Suit::Hearts)],
+            vec![card(Rank::Jack, Suit::Diamonds)],
+        ]);
+        // Валет бьёт туза
+        let result = solve(&state);
+        assert_eq!(result.team_points, HashMap::from([(1, 0), (2, 23)]));
+        assert_eq!(result.best, Some(card(Rank::Ace, Suit::Hearts)));
+    }
+
+    #[test]
+    fn cashing_the_ace_first_saves_eleven_points() {
+        let state = ending(Suit::Hearts, [
+            vec![card(Rank::Ace, Suit::Spades), card(Rank::Ten, Suit::Diamonds)],
+            vec![card(Rank::King, Suit::Diamonds), card(Rank::Seven, Suit::Clubs)],
+            vec![card(Rank::Seven, Suit::Spades), card(Rank::Eight, Suit::Clubs)],
+            vec![card(Rank::Ace, Suit::Diamonds), card(Rank::Nine, Suit::Clubs)],
+        ]);
+        let result = solve(&state);
+        assert_eq!(result.team_points, HashMap::from([(1, 11), (2, 25)]));
+        assert_eq!(result.best, Some(card(Rank::Ace, Suit::Spades)));
+        let mut moves = evaluate_moves(&state);
+        moves.sort_by_key(|(_, points)| *points);
+        assert_eq!(moves, [(card(Rank::Ten, Suit::Diamonds), 0), (card(Rank::Ace, Suit::Spades), 11)]);
+    }
+
+    #[test]
+    fn table_search_matches_plain_minimax() {
+        for seed in 0..20 {
+            let mut state = GameState::new_with(Suit::Spades, &mut StdRng::seed_from_u64(seed));
+            // Играем до трёх последних взяток, иногда останавливаясь посреди взятки
+            let plays = 20 + (seed % 3) as usize;
+            for _ in 0..plays {
+                let pos = state.current_turn;
+                state.play_card(pos, state.legal_cards(pos)[seed as usize % state.legal_cards(pos).len()]).unwrap();
+                state.resolve_trick();
+            }
+
+            let result = solve(&state);
+            let expected = brute_force(&state);
+            assert_eq!(result.team_points[&1], expected, "seed {seed}");
+
+            let mover_team = state.current_turn.team();
+            for (card, points) in evaluate_moves(&state) {
+                let mut next = state.clone();
+                next.play_card(state.current_turn, card).unwrap();
+                let trick: u32 = next.current_trick.iter().map(|(_, c)| c.points() as u32).sum();
+                let team_1 = match next.resolve_trick() {
+                    Some(winner) if winner.team() == 1 => trick,
+                    _ => 0,
+                } + brute_force(&next);
+                let remaining = result.team_points[&1] + result.team_points[&2];
+                let expected = if mover_team == 1 { team_1 } else { remaining - team_1 };
+                assert_eq!(points, expected, "seed {seed}, {card:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Rules fuzzer: drives random legal (and some illegal) plays through
+//! `GameState` and checks the game invariants after every step.
+//!
+//! Usage: `fuzz [--cases N] [--seed S]` runs cases `S..S+N`;
+//! `fuzz --replay S` reruns a single case and prints every play.
+//! A failing case prints its seed, so it can be replayed exactly.
+
+use std::collections::HashSet;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use squirrel_core::core::manager::EYES_TO_WIN;
+use squirrel_core::utils::schemas::{full_deck, jack_priority, Card, GameState, PlayerPosition, Rank, Suit};
+
+/// Card points in one deal.
+const DEAL_POINTS: u32 = 120;
+/// Games longer than this are reported, eyes must have stopped growing.
+const MAX_DEALS: u32 = 200;
+
+struct Violation {
+    deal: u32,
+    play: usize,
+    message: String,
+}
+
+/// Independent trick evaluation: jacks by `jack_priority`, then trumps by
+/// rank, then the lead suit by rank.
+fn reference_winner(trick: &[(PlayerPosition, Card)], trump: Suit) -> PlayerPosition {
+    let lead = trick[0].1.suit;
+    let mut best = trick[0];
+    for &(pos, card) in &trick[1..] {
+        let (_, top) = best;
+        let beats = match (card.rank == Rank::Jack, top.rank == Rank::Jack) {
+            (true, true) => jack_priority(card.suit) > jack_priority(top.suit),
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => {
+                if card.suit == top.suit {
+                    card.rank > top.rank
+                } else {
+                    card.suit == trump || (card.suit == lead && top.suit != trump)
+                }
+            }
+        };
+        if beats {
+            best = (pos, card);
+        }
+    }
+    best.0
+}
+
+/// Every card is exactly once in a hand, the trick on the table or a
+/// team's taken pile, and the piles agree with the plays of the deal.
+fn check_cards(state: &GameState) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    let held = state.hands.values().flatten();
+    let on_table = state.current_trick.iter().map(|(_, c)| c);
+    let taken = state.taken.values().flatten();
+    for card in held.chain(on_table).chain(taken) {
+        if !seen.insert(*card) {
+            return Err(format!("{card:?} is in play twice"));
+        }
+    }
+    if seen.len() != 32 || full_deck().iter().any(|c| !seen.contains(c)) {
+        return Err(format!("{} cards in play instead of 32", seen.len()));
+    }
+    let sizes: HashSet<usize> = state.hands.values().map(Vec::len).collect();
+    if state.current_trick.is_empty() && sizes.len() != 1 {
+        return Err(format!("unequal hands at trick start: {sizes:?}"));
+    }
+    let piled: usize = state.taken.values().map(Vec::len).sum();
+    if piled + state.current_trick.len() != state.deal_plays.len() {
+        return Err(format!("{piled} cards taken, {} played", state.deal_plays.len()));
+    }
+    for team in [1, 2] {
+        if state.team_scores[&team] != state.deal_points(team) {
+            return Err(format!("team {team} score {} differs from its pile", state.team_scores[&team]));
+        }
+    }
+    Ok(())
+}
+
+/// Tries a card the rules forbid and checks it is refused without effect.
+fn check_illegal_play(state: &mut GameState, rng: &mut StdRng) -> Result<(), String> {
+    let pos = state.current_turn;
+    let legal = state.legal_cards(pos);
+    let illegal: Vec<(PlayerPosition, Card)> = full_deck().into_iter()
+        .filter(|c| !legal.contains(c))
+        .map(|c| (pos, c))
+        .chain(state.hands[&pos.next()].iter().map(|c| (pos.next(), *c)))
+        .collect();
+    let Some(&(player, card)) = illegal.choose(rng) else {
+        return Ok(());
+    };
+
+    let hands = state.hands.clone();
+    let trick = state.current_trick.clone();
+    if state.play_card(player, card).is_ok() {
+        return Err(format!("illegal play {card:?} by {player:?} was accepted"));
+    }
+    if state.hands != hands || state.current_trick != trick || state.current_turn != pos {
+        return Err(format!("refused play {card:?} by {player:?} changed the state"));
+    }
+    Ok(())
+}
+
+fn run_case(seed: u64, verbose: bool) -> Result<(), Violation> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut state = GameState::new_with(Suit::random_suit_with(&mut rng), &mut rng);
+    let mut eyes = (0, 0);
+
+    for deal in 0..MAX_DEALS {
+        let fail = |play: usize, message: String| Violation { deal, play, message };
+        if verbose {
+            println!("deal {deal}, trump {:?}", state.trump);
+        }
+
+        let mut play = 0;
+        while state.hands.values().any(|h| !h.is_empty()) {
+            check_cards(&state).map_err(|m| fail(play, m))?;
+            if rng.gen_bool(0.2) {
+                check_illegal_play(&mut state, &mut rng).map_err(|m| fail(play, m))?;
+            }
+
+            let pos = state.current_turn;
+            let card = *state.legal_cards(pos).choose(&mut rng).ok_or_else(|| fail(play, format!("{pos:?} has no legal card")))?;
+            if verbose {
+                println!("  {pos:?} plays {card:?}");
+            }
+            state.play_card(pos, card).map_err(|e| fail(play, format!("legal play {card:?} by {pos:?} refused: {e}")))?;
+            play += 1;
+
+            if state.current_trick.len() < 4 {
+                if state.current_turn != pos.next() {
+                    return Err(fail(play, format!("{:?} on move after {pos:?}", state.current_turn)));
+                }
+                continue;
+            }
+            let expected = reference_winner(&state.current_trick, state.trump);
+            let winner = state.resolve_trick().ok_or_else(|| fail(play, "full trick not resolved".to_string()))?;
+            if winner != expected {
+                return Err(fail(play, format!("trick won by {winner:?}, rules say {expected:?}")));
+            }
+            if state.current_turn != winner {
+                return Err(fail(play, format!("{:?} leads after a trick won by {winner:?}", state.current_turn)));
+            }
+            if state.last_trick_winner() != Some(winner) {
+                return Err(fail(play, "last trick not kept".to_string()));
+            }
+        }
+        check_cards(&state).map_err(|m| fail(play, m))?;
+
+        let points = state.deal_points(1) + state.deal_points(2);
+        if points != DEAL_POINTS {
+            return Err(fail(play, format!("deal worth {points} points instead of {DEAL_POINTS}")));
+        }
+        let tricks = state.tricks_taken(1) + state.tricks_taken(2);
+        if tricks != 8 {
+            return Err(fail(play, format!("{tricks} tricks taken instead of 8")));
+        }
+
+        state.update_eye_after_round();
+        let now = (state.team_eye[&1], state.team_eye[&2]);
+        if now.0 < eyes.0 || now.1 < eyes.1 {
+            return Err(fail(play, format!("eyes went down from {eyes:?} to {now:?}")));
+        }
+        eyes = now;
+        if verbose {
+            println!("  eyes {eyes:?}");
+        }
+        if eyes.0 >= EYES_TO_WIN || eyes.1 >= EYES_TO_WIN {
+            return Ok(());
+        }
+
+        state.trump = Suit::random_suit_with(&mut rng);
+        state.update_hands_with(&mut rng);
+        if state.team_scores.values().any(|s| *s != 0) || state.taken.values().any(|p| !p.is_empty()) {
+            return Err(fail(0, "new deal starts with points taken".to_string()));
+        }
+    }
+    Err(Violation { deal: MAX_DEALS, play: 0, message: format!("no winner after {MAX_DEALS} deals") })
+}
+
+fn main() {
+    let mut cases = 10_000u64;
+    let mut seed = 0u64;
+    let mut replay = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().and_then(|v| v.parse().ok());
+        match (flag.as_str(), value) {
+            ("--cases", Some(v)) => cases = v,
+            ("--seed", Some(v)) => seed = v,
+            ("--replay", Some(v)) => replay = Some(v),
+            _ => {
+                eprintln!("usage: fuzz [--cases N] [--seed S] | fuzz --replay S");
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(seed) = replay {
+        seed_range(seed, 1, true);
+    } else {
+        seed_range(seed, cases, false);
+    }
+}
+
+fn seed_range(start: u64, cases: u64, verbose: bool) {
+    for seed in start..start.saturating_add(cases) {
+        if let Err(v) = run_case(seed, verbose) {
+            eprintln!("invariant violated: {}", v.message);
+            eprintln!("seed {seed}, deal {}, play {}", v.deal, v.play);
+            eprintln!("replay with: fuzz --replay {seed}");
+            std::process::exit(1);
+        }
+    }
+    println!("{cases} cases from seed {start}: all invariants hold");
+}
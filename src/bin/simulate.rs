@@ -0,0 +1,192 @@
+//! Headless self-play: runs complete games between bot strategies straight
+//! on `GameState` and prints aggregate statistics.
+//!
+//! Usage: `simulate [--games N] [--threads T] [--seed S] [--ns STRATEGY] [--ew STRATEGY]`
+//! Strategies: random, heuristic, montecarlo-easy, montecarlo-medium, montecarlo-hard
+//! and external:<name> for bots registered in `EXTERNAL_BOTS`.
+//! Game `i` is dealt from seed `S + i`, so a run is reproducible whatever
+//! the number of threads.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use squirrel_core::ai::external::{parse_external_bots, register_external_bots, DEFAULT_MOVE_TIMEOUT};
+use squirrel_core::ai::{BotKind, PlayerView, Strategy};
+use squirrel_core::core::manager::{ALL_POSITIONS, EYES_TO_WIN};
+use squirrel_core::utils::schemas::{GameState, Suit};
+
+/// Games still undecided after this many deals are counted as unfinished.
+const MAX_DEALS: u32 = 200;
+
+struct Options {
+    games: u64,
+    threads: usize,
+    seed: u64,
+    ns: BotKind,
+    ew: BotKind,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        games: 1000,
+        threads: num_cpus::get(),
+        seed: 0,
+        ns: BotKind::Heuristic,
+        ew: BotKind::Random,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("{flag} needs a value"))?;
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(|_| "bad --games")?,
+            "--threads" => options.threads = value.parse().map_err(|_| "bad --threads")?,
+            "--seed" => options.seed = value.parse().map_err(|_| "bad --seed")?,
+            "--ns" => options.ns = value.parse()?,
+            "--ew" => options.ew = value.parse()?,
+            _ => return Err(format!("unknown option {flag}")),
+        }
+    }
+    options.threads = options.threads.max(1);
+    Ok(options)
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    games: u64,
+    /// Games won by team 1 (N/S) and team 2 (E/W).
+    wins: [u64; 2],
+    unfinished: u64,
+    deals: u64,
+    eyes_awarded: u64,
+    /// Deals that ended 60:60.
+    eggs: u64,
+    /// Deals won by the team playing with the trump.
+    trump_team_deals_won: u64,
+    trump_team_points: u64,
+    other_team_points: u64,
+}
+
+impl Stats {
+    fn merge(&mut self, other: &Stats) {
+        self.games += other.games;
+        self.wins[0] += other.wins[0];
+        self.wins[1] += other.wins[1];
+        self.unfinished += other.unfinished;
+        self.deals += other.deals;
+        self.eyes_awarded += other.eyes_awarded;
+        self.eggs += other.eggs;
+        self.trump_team_deals_won += other.trump_team_deals_won;
+        self.trump_team_points += other.trump_team_points;
+        self.other_team_points += other.other_team_points;
+    }
+}
+
+fn team_total(state: &GameState, team: u8) -> u32 {
+    state.team_eye.get(&team).copied().unwrap_or(0)
+}
+
+/// Plays one game the way a server room does and adds it to `stats`.
+fn play_game(seed: u64, options: &Options, stats: &mut Stats) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut state = GameState::new_with(Suit::Hearts, &mut rng);
+    let mut seats: Vec<Box<dyn Strategy>> = ALL_POSITIONS.iter().enumerate()
+        .map(|(i, pos)| {
+            let kind = if pos.team() == 1 { options.ns } else { options.ew };
+            kind.create_seeded(seed.wrapping_mul(4).wrapping_add(i as u64))
+        })
+        .collect();
+
+    stats.games += 1;
+    for _ in 0..MAX_DEALS {
+        let eyes_before = team_total(&state, 1) + team_total(&state, 2);
+
+        while state.hands.values().any(|h| !h.is_empty()) {
+            let pos = state.current_turn;
+            let seat = ALL_POSITIONS.iter().position(|p| *p == pos).expect("seat is listed");
+            let card = seats[seat].choose_card(&PlayerView::from_state(&state, pos));
+            state.play_card(pos, card).expect("strategies play legal cards");
+            state.resolve_trick();
+        }
+
+        let points = [state.deal_points(1), state.deal_points(2)];
+        let trump_team = state.trump_team() as usize - 1;
+        stats.deals += 1;
+        stats.trump_team_points += points[trump_team] as u64;
+        stats.other_team_points += points[1 - trump_team] as u64;
+        if points[0] == points[1] {
+            stats.eggs += 1;
+        } else if points[trump_team] > points[1 - trump_team] {
+            stats.trump_team_deals_won += 1;
+        }
+
+        state.update_eye_after_round();
+        stats.eyes_awarded += (team_total(&state, 1) + team_total(&state, 2) - eyes_before) as u64;
+
+        if team_total(&state, 1) >= EYES_TO_WIN || team_total(&state, 2) >= EYES_TO_WIN {
+            let winner = if team_total(&state, 1) >= EYES_TO_WIN { 0 } else { 1 };
+            stats.wins[winner] += 1;
+            return;
+        }
+        state.trump = Suit::random_suit_with(&mut rng);
+        state.update_hands_with(&mut rng);
+    }
+    stats.unfinished += 1;
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { 100.0 * part as f64 / total as f64 }
+}
+
+fn main() {
+    let external = std::env::var("EXTERNAL_BOTS").unwrap_or_default();
+    register_external_bots(parse_external_bots(&external, DEFAULT_MOVE_TIMEOUT));
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("simulate: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    let started = Instant::now();
+    let next_game = AtomicU64::new(0);
+    let mut total = Stats::default();
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.threads)
+            .map(|_| scope.spawn(|| {
+                let mut stats = Stats::default();
+                loop {
+                    let game = next_game.fetch_add(1, Ordering::Relaxed);
+                    if game >= options.games {
+                        return stats;
+                    }
+                    play_game(options.seed.wrapping_add(game), &options, &mut stats);
+                }
+            }))
+            .collect();
+        for worker in workers {
+            total.merge(&worker.join().expect("simulation thread panicked"));
+        }
+    });
+
+    let ns = options.ns.name();
+    let ew = options.ew.name();
+    println!("{} games, seed {}, {} threads, {:?}", total.games, options.seed, options.threads, started.elapsed());
+    println!();
+    println!("Team 1 (N/S, {ns}): {:.1}% wins", percent(total.wins[0], total.games));
+    println!("Team 2 (E/W, {ew}): {:.1}% wins", percent(total.wins[1], total.games));
+    if total.unfinished > 0 {
+        println!("Unfinished after {MAX_DEALS} deals: {}", total.unfinished);
+    }
+    println!();
+    println!("Deals per game: {:.2}", total.deals as f64 / total.games.max(1) as f64);
+    println!("Eyes per deal: {:.2}", total.eyes_awarded as f64 / total.deals.max(1) as f64);
+    println!("Eggs (60:60): {:.2}% of deals", percent(total.eggs, total.deals));
+    println!(
+        "Trump team: wins {:.1}% of deals, {:.1} vs {:.1} points per deal",
+        percent(total.trump_team_deals_won, total.deals),
+        total.trump_team_points as f64 / total.deals.max(1) as f64,
+        total.other_team_points as f64 / total.deals.max(1) as f64,
+    );
+}
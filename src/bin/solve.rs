@@ -0,0 +1,139 @@
+//! Double-dummy analysis of a single deal.
+//!
+//! Usage: `solve [<trump> <north> <east> <south> <west> [<leader>]]`
+//! where hands are comma separated cards such as `AH,10H,JC,7S` or `A♥,10♥`.
+//! Without arguments a random deal is analyzed.
+
+use std::time::Instant;
+use squirrel_core::ai::solver::{evaluate_moves, solve};
+use squirrel_core::utils::schemas::{Card, GameState, PlayerPosition, Rank, Suit};
+
+const SEATS: [PlayerPosition; 4] = [PlayerPosition::North, PlayerPosition::East, PlayerPosition::South, PlayerPosition::West];
+
+fn parse_suit(s: &str) -> Result<Suit, String> {
+    match s.to_ascii_uppercase().as_str() {
+        "C" | "CLUBS" | "♣" => Ok(Suit::Clubs),
+        "D" | "DIAMONDS" | "♦" => Ok(Suit::Diamonds),
+        "H" | "HEARTS" | "♥" => Ok(Suit::Hearts),
+        "S" | "SPADES" | "♠" => Ok(Suit::Spades),
+        _ => Err(format!("unknown suit {s}")),
+    }
+}
+
+fn parse_card(s: &str) -> Result<Card, String> {
+    let s = s.trim();
+    // Масть — последний символ, он может быть многобайтным (♥)
+    let (split, _) = s.char_indices().last().ok_or("empty card")?;
+    let rank = match &s[..split].to_ascii_uppercase()[..] {
+        "7" => Rank::Seven,
+        "8" => Rank::Eight,
+        "9" => Rank::Nine,
+        "10" | "T" => Rank::Ten,
+        "J" => Rank::Jack,
+        "Q" => Rank::Queen,
+        "K" => Rank::King,
+        "A" => Rank::Ace,
+        r => return Err(format!("unknown rank {r}")),
+    };
+    Ok(Card { suit: parse_suit(&s[split..])?, rank })
+}
+
+fn parse_position(s: &str) -> Result<PlayerPosition, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "n" | "north" => Ok(PlayerPosition::North),
+        "e" | "east" => Ok(PlayerPosition::East),
+        "s" | "south" => Ok(PlayerPosition::South),
+        "w" | "west" => Ok(PlayerPosition::West),
+        _ => Err(format!("unknown position {s}")),
+    }
+}
+
+fn card_name(card: Card) -> String {
+    let rank = match card.rank {
+        Rank::Seven => "7",
+        Rank::Eight => "8",
+        Rank::Nine => "9",
+        Rank::Ten => "10",
+        Rank::Jack => "J",
+        Rank::Queen => "Q",
+        Rank::King => "K",
+        Rank::Ace => "A",
+    };
+    format!("{rank}{}", &format!("{:?}", card.suit)[..1])
+}
+
+fn build_state(args: &[String]) -> Result<GameState, String> {
+    if args.is_empty() {
+        return Ok(GameState::new(Suit::random_suit()));
+    }
+    if args.len() < 5 {
+        return Err("expected <trump> <north> <east> <south> <west> [<leader>]".to_string());
+    }
+
+    let mut state = GameState::new(parse_suit(&args[0])?);
+    let mut seen = vec![];
+    for (pos, hand) in SEATS.iter().zip(&args[1..5]) {
+        let cards = hand.split(',').map(parse_card).collect::<Result<Vec<_>, _>>()?;
+        if let Some(card) = cards.iter().find(|c| seen.contains(*c)) {
+            return Err(format!("{} is dealt twice", card_name(*card)));
+        }
+        seen.extend(cards.iter().copied());
+        state.hands.insert(*pos, cards);
+    }
+    let sizes: Vec<usize> = state.hands.values().map(Vec::len).collect();
+    if sizes.iter().any(|n| *n != sizes[0]) {
+        return Err("all hands must have the same number of cards".to_string());
+    }
+    if let Some(leader) = args.get(5) {
+        state.current_turn = parse_position(leader)?;
+    }
+    Ok(state)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let state = match build_state(&args) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("solve: {e}");
+            std::process::exit(2);
+        }
+    };
+
+    println!("Trump: {:?}", state.trump);
+    for pos in SEATS {
+        let hand: Vec<String> = state.hands[&pos].iter().map(|c| card_name(*c)).collect();
+        println!("{pos:?}: {}", hand.join(" "));
+    }
+    println!("{:?} to lead", state.current_turn);
+
+    let started = Instant::now();
+    let result = solve(&state);
+    println!();
+    println!("Team 1 (N/S): {} points", result.team_points[&1]);
+    println!("Team 2 (E/W): {} points", result.team_points[&2]);
+    println!("Solved in {:?}, {} nodes", started.elapsed(), result.nodes);
+
+    println!();
+    let mut moves = evaluate_moves(&state);
+    moves.sort_by_key(|(_, points)| std::cmp::Reverse(*points));
+    for (card, points) in moves {
+        let mark = if Some(card) == result.best { " *" } else { "" };
+        println!("{:>4}  {points}{mark}", card_name(card));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_letter_and_symbol_suits() {
+        assert_eq!(parse_card("10H"), Ok(Card { suit: Suit::Hearts, rank: Rank::Ten }));
+        assert_eq!(parse_card("10♥"), Ok(Card { suit: Suit::Hearts, rank: Rank::Ten }));
+        assert_eq!(parse_card(" j♣"), Ok(Card { suit: Suit::Clubs, rank: Rank::Jack }));
+        assert!(parse_card("♥").is_err());
+        assert!(parse_card("1♥0").is_err());
+        assert!(parse_card("").is_err());
+    }
+}
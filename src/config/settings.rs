@@ -1,17 +1,456 @@
-use std::env;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
 
+/// Settings file read when `SETTINGS_FILE` is not set; it may be missing.
+const DEFAULT_SETTINGS_FILE: &str = "settings.toml";
+/// Shortest JWT secret accepted, in bytes.
+pub const MIN_SECRET_LEN: usize = 32;
+/// `kid` of the key made from `SECRET_KEY` when no keys are configured.
+pub const DEFAULT_KID: &str = "default";
+/// Telegram's Ed25519 key for third-party validation of initData.
+pub const TELEGRAM_PUBLIC_KEY: &str = "e7bf03a2fa4602af4580703d88dda5bb59f32ed8b02a56c187fe7d34caed242d";
 
-#[derive(Debug, Clone)]
+/// What `Debug` prints instead of a secret that is set.
+fn redacted(secret: &str) -> &'static str {
+    if secret.is_empty() { "" } else { "<redacted>" }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct PostgresSettings {
+    pub user: String,
+    pub password: String,
+    pub host: String,
+    pub port: u16,
+    pub db: String,
+    pub max_connections: u32,
+}
+
+impl Default for PostgresSettings {
+    fn default() -> Self {
+        Self {
+            user: String::new(),
+            password: String::new(),
+            host: "localhost".to_string(),
+            port: 5432,
+            db: String::new(),
+            max_connections: 256,
+        }
+    }
+}
+
+impl fmt::Debug for PostgresSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresSettings")
+            .field("user", &self.user)
+            .field("password", &redacted(&self.password))
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("db", &self.db)
+            .field("max_connections", &self.max_connections)
+            .finish()
+    }
+}
+
+impl PostgresSettings {
+    /// Connection URL; the user and password may hold any characters.
+    pub fn url(&self) -> String {
+        format!(
+            "postgresql://{}:{}@{}:{}/{}",
+            utf8_percent_encode(&self.user, NON_ALPHANUMERIC),
+            utf8_percent_encode(&self.password, NON_ALPHANUMERIC),
+            self.host,
+            self.port,
+            self.db
+        )
+    }
+}
+
+/// A JWT key. HS256 keys have a `secret`; EdDSA and RS256 keys are PEM
+/// files, and a key with only the public half is used for verification.
+#[derive(Clone, Deserialize)]
+pub struct JwtKeySettings {
+    pub kid: String,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: String,
+    pub secret: Option<String>,
+    pub private_key_file: Option<String>,
+    pub public_key_file: Option<String>,
+}
+
+impl fmt::Debug for JwtKeySettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtKeySettings")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .field("secret", &self.secret.as_deref().map(redacted))
+            .field("private_key_file", &self.private_key_file)
+            .field("public_key_file", &self.public_key_file)
+            .finish()
+    }
+}
+
+fn default_algorithm() -> String {
+    "HS256".to_string()
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct JwtSettings {
+    pub issuer: String,
+    pub audience: String,
+    /// Key new tokens are signed with; the others only verify, which lets
+    /// tokens signed with a retired key live out their time.
+    pub signing_kid: String,
+    /// When empty, `SECRET_KEY` is the only key.
+    pub keys: Vec<JwtKeySettings>,
+    /// HS256 secret of the tokens issued before they carried a `kid`, `iss`
+    /// and `aud`; those are accepted until they expire. Unset, they are
+    /// rejected and their holders have to log in again.
+    pub legacy_secret: Option<String>,
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        Self {
+            issuer: "squirrel-core".to_string(),
+            audience: "squirrel".to_string(),
+            signing_kid: DEFAULT_KID.to_string(),
+            keys: vec![],
+            legacy_secret: None,
+        }
+    }
+}
+
+impl fmt::Debug for JwtSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtSettings")
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("signing_kid", &self.signing_kid)
+            .field("keys", &self.keys)
+            .field("legacy_secret", &self.legacy_secret.as_deref().map(redacted))
+            .finish()
+    }
+}
+
+/// Server configuration. Loaded once at startup from an optional TOML file,
+/// then the environment (and `.env`), which wins over the file.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
 pub struct AppSettings {
-    secret_key: String,
-    bot_token: String,
+    pub bind_addr: SocketAddr,
+    /// Origins allowed by CORS; empty or `*` allows any.
+    pub cors_origins: Vec<String>,
+    pub log_filter: String,
+    /// Key the JWTs are signed with unless `jwt.keys` are configured.
+    pub secret_key: String,
+    pub jwt: JwtSettings,
+    /// Without it initData is checked by Telegram's Ed25519 signature,
+    /// which needs `telegram_bot_id` instead.
+    pub bot_token: String,
+    /// Needed with `bot_token` only; invite links are made without it.
+    pub bot_username: String,
+    /// Id of the bot the Mini App belongs to; taken from `bot_token` when unset.
+    pub telegram_bot_id: Option<u64>,
+    /// Hex Ed25519 keys accepted for initData signatures.
+    pub telegram_public_keys: Vec<String>,
+    /// Oldest Telegram initData accepted for login, by its `auth_date`.
+    pub telegram_max_auth_age_secs: u64,
+    pub postgres: PostgresSettings,
+    /// External bot processes, `name=program args;...`.
+    pub external_bots: String,
+    pub external_bot_timeout_ms: u64,
+    /// How long running deals may take to finish on shutdown.
+    pub shutdown_drain_secs: u64,
+    /// Post-game analyses computed at once; more requests get 503.
+    pub max_concurrent_analyses: usize,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            bind_addr: SocketAddr::from(([0, 0, 0, 0], 9221)),
+            cors_origins: vec![],
+            log_filter: "debug".to_string(),
+            secret_key: String::new(),
+            jwt: JwtSettings::default(),
+            bot_token: String::new(),
+            bot_username: String::new(),
+            telegram_bot_id: None,
+            telegram_public_keys: vec![TELEGRAM_PUBLIC_KEY.to_string()],
+            telegram_max_auth_age_secs: 24 * 3600,
+            postgres: PostgresSettings::default(),
+            external_bots: String::new(),
+            external_bot_timeout_ms: 2000,
+            shutdown_drain_secs: 60,
+            max_concurrent_analyses: 2,
+        }
+    }
+}
+
+impl fmt::Debug for AppSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppSettings")
+            .field("bind_addr", &self.bind_addr)
+            .field("cors_origins", &self.cors_origins)
+            .field("log_filter", &self.log_filter)
+            .field("secret_key", &redacted(&self.secret_key))
+            .field("jwt", &self.jwt)
+            .field("bot_token", &redacted(&self.bot_token))
+            .field("bot_username", &self.bot_username)
+            .field("telegram_bot_id", &self.telegram_bot_id)
+            .field("telegram_public_keys", &self.telegram_public_keys)
+            .field("telegram_max_auth_age_secs", &self.telegram_max_auth_age_secs)
+            .field("postgres", &self.postgres)
+            .field("external_bots", &self.external_bots)
+            .field("external_bot_timeout_ms", &self.external_bot_timeout_ms)
+            .field("shutdown_drain_secs", &self.shutdown_drain_secs)
+            .field("max_concurrent_analyses", &self.max_concurrent_analyses)
+            .finish()
+    }
+}
+
+/// Everything wrong with the configuration, reported at once.
+#[derive(Debug)]
+pub struct SettingsError(pub Vec<String>);
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl AppSettings {
+    pub fn load() -> Result<Self, SettingsError> {
+        dotenvy::dotenv().ok();
+        let mut problems = vec![];
+
+        let path = env_value("SETTINGS_FILE");
+        let mut settings = match &path {
+            Some(path) => read_file(Path::new(path), &mut problems),
+            None if Path::new(DEFAULT_SETTINGS_FILE).exists() => read_file(Path::new(DEFAULT_SETTINGS_FILE), &mut problems),
+            None => AppSettings::default(),
+        };
+        settings.apply_env(&mut problems);
+        settings.validate(&mut problems);
+
+        if problems.is_empty() { Ok(settings) } else { Err(SettingsError(problems)) }
+    }
+
+    fn apply_env(&mut self, problems: &mut Vec<String>) {
+        let string = |name: &str, field: &mut String| {
+            if let Some(value) = env_value(name) {
+                *field = value;
+            }
+        };
+        string("SECRET_KEY", &mut self.secret_key);
+        string("JWT_ISSUER", &mut self.jwt.issuer);
+        string("JWT_AUDIENCE", &mut self.jwt.audience);
+        string("JWT_SIGNING_KID", &mut self.jwt.signing_kid);
+        if let Some(secret) = env_value("JWT_LEGACY_SECRET") {
+            self.jwt.legacy_secret = Some(secret);
+        }
+        string("BOT_TOKEN", &mut self.bot_token);
+        string("BOT_USERNAME", &mut self.bot_username);
+        string("LOG_FILTER", &mut self.log_filter);
+        string("EXTERNAL_BOTS", &mut self.external_bots);
+        string("POSTGRES_USER", &mut self.postgres.user);
+        string("POSTGRES_PASSWORD", &mut self.postgres.password);
+        string("POSTGRES_HOST", &mut self.postgres.host);
+        string("POSTGRES_DB", &mut self.postgres.db);
+
+        parse_env("BIND_ADDR", &mut self.bind_addr, problems);
+        parse_env("POSTGRES_PORT", &mut self.postgres.port, problems);
+        parse_env("POSTGRES_MAX_CONNECTIONS", &mut self.postgres.max_connections, problems);
+        parse_env("EXTERNAL_BOT_TIMEOUT_MS", &mut self.external_bot_timeout_ms, problems);
+        parse_env("SHUTDOWN_DRAIN_SECS", &mut self.shutdown_drain_secs, problems);
+        parse_env("MAX_CONCURRENT_ANALYSES", &mut self.max_concurrent_analyses, problems);
+        if let Some(value) = env_value("TELEGRAM_BOT_ID") {
+            match value.parse() {
+                Ok(id) => self.telegram_bot_id = Some(id),
+                Err(_) => problems.push(format!("TELEGRAM_BOT_ID={value} is not valid")),
+            }
+        }
+        if let Some(keys) = env_value("TELEGRAM_PUBLIC_KEYS") {
+            self.telegram_public_keys = keys.split(',').map(str::trim).filter(|k| !k.is_empty()).map(str::to_string).collect();
+        }
+        parse_env("TELEGRAM_MAX_AUTH_AGE_SECS", &mut self.telegram_max_auth_age_secs, problems);
+        if let Some(origins) = env_value("CORS_ORIGINS") {
+            self.cors_origins = origins.split(',').map(str::trim).filter(|o| !o.is_empty()).map(str::to_string).collect();
+        }
+    }
+
+    /// Checks what can be checked without building anything; the JWT keys,
+    /// Telegram keys and external bots are checked where they are built.
+    fn validate(&self, problems: &mut Vec<String>) {
+        if self.jwt.keys.is_empty() && self.secret_key.len() < MIN_SECRET_LEN {
+            problems.push(format!("SECRET_KEY must be at least {MIN_SECRET_LEN} bytes"));
+        }
+        for (name, value) in [
+            ("POSTGRES_USER", &self.postgres.user),
+            ("POSTGRES_HOST", &self.postgres.host),
+            ("POSTGRES_DB", &self.postgres.db),
+        ] {
+            if value.trim().is_empty() {
+                problems.push(format!("{name} is not set"));
+            }
+        }
+        if self.postgres.max_connections == 0 {
+            problems.push("POSTGRES_MAX_CONNECTIONS must be positive".to_string());
+        }
+        if self.bot_token.trim().is_empty() && self.telegram_bot_id.is_none() {
+            problems.push("set BOT_TOKEN, or TELEGRAM_BOT_ID to check initData by signature only".to_string());
+        }
+        if !self.bot_token.trim().is_empty() && self.bot_id_from_token().is_none() {
+            problems.push("BOT_TOKEN is not <bot id>:<secret>".to_string());
+        }
+        // В режиме проверки только подписи имя бота не нужно
+        if !self.bot_token.trim().is_empty() && self.bot_username.trim().is_empty() {
+            problems.push("BOT_USERNAME is not set".to_string());
+        }
+        if self.telegram_public_keys.is_empty() {
+            problems.push("TELEGRAM_PUBLIC_KEYS is empty".to_string());
+        }
+        if self.telegram_max_auth_age_secs == 0 {
+            problems.push("TELEGRAM_MAX_AUTH_AGE_SECS must be positive".to_string());
+        }
+        if self.external_bot_timeout_ms == 0 {
+            problems.push("EXTERNAL_BOT_TIMEOUT_MS must be positive".to_string());
+        }
+        if self.max_concurrent_analyses == 0 {
+            problems.push("MAX_CONCURRENT_ANALYSES must be positive".to_string());
+        }
+        for origin in &self.cors_origins {
+            if origin != "*" && origin.parse::<axum::http::HeaderValue>().is_err() {
+                problems.push(format!("CORS origin `{origin}` is not a valid header value"));
+            }
+        }
+    }
+
+    pub fn external_bot_timeout(&self) -> Duration {
+        Duration::from_millis(self.external_bot_timeout_ms)
+    }
+
+    fn bot_id_from_token(&self) -> Option<u64> {
+        self.bot_token.split_once(':').and_then(|(id, _)| id.parse().ok())
+    }
+
+    pub fn telegram_bot_id(&self) -> Option<u64> {
+        self.telegram_bot_id.or_else(|| self.bot_id_from_token())
+    }
+
+    pub fn telegram_max_auth_age(&self) -> Duration {
+        Duration::from_secs(self.telegram_max_auth_age_secs)
+    }
+
+    pub fn shutdown_drain(&self) -> Duration {
+        Duration::from_secs(self.shutdown_drain_secs)
+    }
+
+    /// CORS with any origin, or just the listed ones.
+    pub fn allows_any_origin(&self) -> bool {
+        self.cors_origins.is_empty() || self.cors_origins.iter().any(|o| o == "*")
+    }
+}
+
+fn read_file(path: &Path, problems: &mut Vec<String>) -> AppSettings {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            problems.push(format!("can not read {}: {e}", path.display()));
+            return AppSettings::default();
+        }
+    };
+    toml::from_str(&text).unwrap_or_else(|e| {
+        problems.push(format!("{}: {e}", path.display()));
+        AppSettings::default()
+    })
+}
+
+/// A set, non-empty environment variable; empty ones from a `.env`
+/// template count as unset.
+fn env_value(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str, field: &mut T, problems: &mut Vec<String>) {
+    if let Some(value) = env_value(name) {
+        match value.parse() {
+            Ok(parsed) => *field = parsed,
+            Err(_) => problems.push(format!("{name}={value} is not valid")),
+        }
+    }
 }
 
-impl AppSettings{
-    async fn new(&self) -> Self{
-        Self{
-            secret_key: env::var("SECRET_KEY").expect("SECRET_KEY not found in ENV"),
-            secret_key: env::var("BOT_TOKEN").expect("SECRET_KEY not found in ENV"),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_hides_secrets() {
+        let mut settings = AppSettings {
+            secret_key: "app-secret-key-app-secret-key-00".to_string(),
+            bot_token: "123:bot-token-secret".to_string(),
+            ..AppSettings::default()
+        };
+        settings.postgres.password = "pg-password-secret".to_string();
+        settings.jwt.keys.push(JwtKeySettings {
+            kid: "old".to_string(),
+            algorithm: "HS256".to_string(),
+            secret: Some("hs256-secret-hs256-secret-hs256-".to_string()),
+            private_key_file: None,
+            public_key_file: None,
+        });
+        settings.jwt.legacy_secret = Some("legacy-secret".to_string());
+
+        let printed = format!("{settings:?}");
+        for secret in ["app-secret-key", "bot-token-secret", "pg-password-secret", "hs256-secret", "legacy-secret"] {
+            assert!(!printed.contains(secret), "{secret} leaked into {printed}");
         }
+        assert!(printed.contains("<redacted>"));
+        assert!(printed.contains("\"old\""));
+    }
+
+    #[test]
+    fn url_escapes_the_password() {
+        let postgres = PostgresSettings {
+            user: "squirrel".to_string(),
+            password: "p@ss:w/rd#1".to_string(),
+            host: "db".to_string(),
+            db: "squirrel".to_string(),
+            ..PostgresSettings::default()
+        };
+        assert_eq!(postgres.url(), "postgresql://squirrel:p%40ss%3Aw%2Frd%231@db:5432/squirrel");
+    }
+
+    #[test]
+    fn bot_username_is_needed_only_with_the_bot_token() {
+        let mut settings = AppSettings {
+            secret_key: "app-secret-key-app-secret-key-00".to_string(),
+            telegram_bot_id: Some(123),
+            ..AppSettings::default()
+        };
+        settings.postgres.user = "squirrel".to_string();
+        settings.postgres.db = "squirrel".to_string();
+        let problems = |settings: &AppSettings| {
+            let mut problems = vec![];
+            settings.validate(&mut problems);
+            problems
+        };
+        assert!(problems(&settings).is_empty());
+
+        settings.bot_token = "123:secret".to_string();
+        assert_eq!(problems(&settings), ["BOT_USERNAME is not set"]);
     }
 }
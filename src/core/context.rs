@@ -1,11 +1,22 @@
-// use crate::core::pool::ConnectionPool;
+use crate::config::settings::AppSettings;
 use crate::core::manager::GameManager;
+use crate::utils::jwt::JwtKeys;
+use ed25519_dalek::VerifyingKey;
 use once_cell::sync::OnceCell;
+use sqlx::PgPool;
 use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 #[derive(Debug)]
 pub struct AppContext{
     game_manager: Arc<GameManager>,
+    db: PgPool,
+    settings: Arc<AppSettings>,
+    jwt: Arc<JwtKeys>,
+    /// Parsed `telegram_public_keys` of the settings.
+    telegram_keys: Vec<VerifyingKey>,
+    /// Slots for post-game analyses, which take a CPU each for seconds.
+    analysis_slots: Arc<Semaphore>,
 }
 
 static GLOBAL_CONTEXT: OnceCell<Arc<AppContext>> = OnceCell::new();
@@ -19,9 +30,14 @@ pub fn get_global_context() -> Arc<AppContext> {
 }
 
 impl AppContext {
-    pub fn new() -> Self{
+    pub fn new(db: PgPool, settings: AppSettings, jwt: JwtKeys, telegram_keys: Vec<VerifyingKey>) -> Self{
         Self{
-            game_manager: Arc::new(GameManager::new()),
+            game_manager: Arc::new(GameManager::new(db.clone())),
+            db,
+            analysis_slots: Arc::new(Semaphore::new(settings.max_concurrent_analyses)),
+            settings: Arc::new(settings),
+            jwt: Arc::new(jwt),
+            telegram_keys,
         }
     }
 
@@ -29,4 +45,24 @@ impl AppContext {
         self.game_manager.clone()
     }
 
+    pub fn db(&self) -> &PgPool{
+        &self.db
+    }
+
+    pub fn settings(&self) -> &AppSettings{
+        &self.settings
+    }
+
+    pub fn jwt(&self) -> &JwtKeys{
+        &self.jwt
+    }
+
+    pub fn telegram_keys(&self) -> &[VerifyingKey]{
+        &self.telegram_keys
+    }
+
+    pub fn analysis_slots(&self) -> Arc<Semaphore>{
+        self.analysis_slots.clone()
+    }
+
 }
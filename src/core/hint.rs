@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+use crate::ai::heuristic::suggest;
+use crate::ai::PlayerView;
+use crate::core::manager::GameManager;
+use crate::utils::schemas::WSEvent;
+
+/// Hints a player may ask for during one game.
+const MAX_HINTS_PER_GAME: u32 = 10;
+/// Minimum pause between two hints of the same player.
+const HINT_COOLDOWN: Duration = Duration::from_secs(5);
+
+impl GameManager {
+    /// Suggests a card to the player on move. Only for unrated tables.
+    pub async fn hint(&self, uid: &str) -> Result<(), &'static str> {
+        let (room, pos) = self.find_room_by_uid(uid).await.ok_or("Not in game")?;
+        if room.rated {
+            return Err("Hints are disabled in rated games");
+        }
+        if room.finished_at.lock().await.is_some() {
+            return Err("Game is over");
+        }
+
+        let view = {
+            let state = room.state.lock().await;
+            if state.current_turn != pos {
+                return Err("Not your turn");
+            }
+            PlayerView::from_state(&state, pos)
+        };
+
+        {
+            let mut hints = room.hints.lock().await;
+            let (used, last) = hints.entry(pos).or_insert((0, None));
+            if *used >= MAX_HINTS_PER_GAME {
+                return Err("No hints left in this game");
+            }
+            if last.is_some_and(|last| last.elapsed() < HINT_COOLDOWN) {
+                return Err("Too many hints, wait a little");
+            }
+            *used += 1;
+            *last = Some(Instant::now());
+        }
+
+        let (card, reason) = suggest(&view);
+        room.send_to(pos, WSEvent::Hint { card, reason: reason.to_string() }).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use sqlx::PgPool;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use crate::core::manager::{GameRoom, PlayerSession, Seat, ALL_POSITIONS};
+
+    /// A table of four humans, with North's events.
+    async fn table(rated: bool) -> (GameManager, Arc<GameRoom>, UnboundedReceiver<WSEvent>) {
+        let manager = GameManager::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let mut north_events = None;
+        let mut seats = HashMap::new();
+        for pos in ALL_POSITIONS {
+            let (sender, receiver) = unbounded_channel();
+            let id = format!("{pos:?}").to_lowercase();
+            seats.insert(pos, Seat::Human(PlayerSession::new(id.clone(), id, false, sender)));
+            if pos == ALL_POSITIONS[0] {
+                north_events = Some(receiver);
+            }
+        }
+        let room = Arc::new(GameRoom::new(seats, rated));
+        manager.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+        (manager, room, north_events.unwrap())
+    }
+
+    #[tokio::test]
+    async fn hints_are_rate_limited_per_game() {
+        let (manager, room, mut events) = table(false).await;
+        assert_eq!(manager.hint("east").await, Err("Not your turn"));
+
+        manager.hint("north").await.unwrap();
+        let Ok(WSEvent::Hint { card, .. }) = events.try_recv() else { panic!("no hint sent") };
+        assert!(room.state.lock().await.legal_cards(ALL_POSITIONS[0]).contains(&card));
+        assert_eq!(manager.hint("north").await, Err("Too many hints, wait a little"));
+
+        // Пауза прошла
+        let earlier = Instant::now().checked_sub(HINT_COOLDOWN).unwrap();
+        room.hints.lock().await.insert(ALL_POSITIONS[0], (1, Some(earlier)));
+        manager.hint("north").await.unwrap();
+
+        room.hints.lock().await.insert(ALL_POSITIONS[0], (MAX_HINTS_PER_GAME, Some(earlier)));
+        assert_eq!(manager.hint("north").await, Err("No hints left in this game"));
+    }
+
+    #[tokio::test]
+    async fn rated_games_have_no_hints() {
+        let (manager, room, mut events) = table(true).await;
+        assert_eq!(manager.hint("north").await, Err("Hints are disabled in rated games"));
+        assert!(events.try_recv().is_err());
+        assert!(room.hints.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::types::Json;
+use sqlx::PgPool;
+use crate::ai::analysis::GameAnalysis;
+use crate::core::manager::ALL_POSITIONS;
+use crate::utils::schemas::{Card, DealRecord, PlayerPosition};
+
+/// A finished game as kept for replays and post-game analysis.
+#[derive(Debug, Clone)]
+pub struct GameHistory {
+    pub game_id: String,
+    /// Player ids in `ALL_POSITIONS` order.
+    pub players: Vec<String>,
+    pub rated: bool,
+    pub winner_team: u8,
+    pub deals: Vec<DealRecord>,
+}
+
+impl GameHistory {
+    pub fn position_of(&self, uid: &str) -> Option<PlayerPosition> {
+        self.players.iter().position(|p| p == uid).map(|i| ALL_POSITIONS[i])
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameSummary {
+    pub game_id: String,
+    pub position: PlayerPosition,
+    pub won: bool,
+    pub rated: bool,
+    /// Unix time in seconds.
+    pub finished_at: i64,
+}
+
+/// Suits, ranks and positions are stored by their variant name.
+fn parse_name<T: DeserializeOwned>(name: String) -> Result<T, sqlx::Error> {
+    serde_json::from_value(serde_json::Value::String(name)).map_err(|e| sqlx::Error::Decode(Box::new(e)))
+}
+
+/// A finished game rebuilt from `game_records`, `game_rounds` and `game_plays`.
+pub async fn load_game_history(pool: &PgPool, game_id: &str) -> Result<Option<GameHistory>, sqlx::Error> {
+    let Some(game) = sqlx::query!(
+        r#"SELECT seats, rated, winner_team AS "winner_team!"
+        FROM game_records
+        WHERE id = $1 AND end_reason = 'finished' AND winner_team IS NOT NULL"#,
+        game_id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let rounds = sqlx::query!(
+        r#"SELECT round, trump, hands AS "hands: Json<HashMap<PlayerPosition, Vec<Card>>>"
+        FROM game_rounds WHERE game_id = $1 ORDER BY round"#,
+        game_id
+    )
+    .fetch_all(pool)
+    .await?;
+    let plays = sqlx::query!(
+        "SELECT round, position, rank, suit FROM game_plays WHERE game_id = $1 ORDER BY round, seq",
+        game_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut deals = Vec::with_capacity(rounds.len());
+    for round in rounds {
+        let mut deal_plays = vec![];
+        for play in plays.iter().filter(|play| play.round == round.round) {
+            let card = Card {
+                suit: parse_name(play.suit.clone())?,
+                rank: parse_name(play.rank.clone())?,
+            };
+            deal_plays.push((parse_name::<PlayerPosition>(play.position.clone())?, card));
+        }
+        // Раунды без сохранённых рук были доиграны до конца: руки = сыгранные карты
+        let hands = match round.hands {
+            Some(hands) => hands.0,
+            None => {
+                let mut hands: HashMap<PlayerPosition, Vec<Card>> = HashMap::new();
+                for (pos, card) in &deal_plays {
+                    hands.entry(*pos).or_default().push(*card);
+                }
+                hands
+            }
+        };
+        deals.push(DealRecord {
+            trump: parse_name(round.trump)?,
+            hands,
+            plays: deal_plays,
+        });
+    }
+
+    Ok(Some(GameHistory {
+        game_id: game_id.to_string(),
+        players: game.seats,
+        rated: game.rated,
+        winner_team: game.winner_team as u8,
+        deals,
+    }))
+}
+
+/// The review stored by `save_game_analysis`, if the game has one yet.
+pub async fn load_game_analysis(pool: &PgPool, game_id: &str) -> Result<Option<GameAnalysis>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT analysis AS "analysis: Json<GameAnalysis>" FROM game_records WHERE id = $1"#,
+        game_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.and_then(|row| row.analysis).map(|analysis| analysis.0))
+}
+
+/// Keeps the review of a finished game, so it is computed only once.
+pub async fn save_game_analysis(pool: &PgPool, game_id: &str, analysis: &GameAnalysis) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE game_records SET analysis = $2 WHERE id = $1",
+        game_id,
+        Json(analysis) as _
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Most recent games `uid` finished, newest first.
+pub async fn list_games(pool: &PgPool, uid: &str, limit: i64) -> Result<Vec<GameSummary>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT id, seats, rated, winner_team AS "winner_team!",
+            EXTRACT(EPOCH FROM finished_at)::BIGINT AS "finished_at!"
+        FROM game_records
+        WHERE seats @> ARRAY[$1] AND end_reason = 'finished' AND winner_team IS NOT NULL
+        ORDER BY finished_at DESC
+        LIMIT $2"#,
+        uid,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter()
+        .filter_map(|row| {
+            let position = ALL_POSITIONS[row.seats.iter().position(|p| p == uid)?];
+            Some(GameSummary {
+                game_id: row.id,
+                won: position.team() as i16 == row.winner_team,
+                position,
+                rated: row.rated,
+                finished_at: row.finished_at,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::db::testing::migrate_until;
+
+    /// Last migration before user ids replaced usernames.
+    const BEFORE_USER_IDS: i64 = 20261019095000;
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs DATABASE_URL"]
+    async fn legacy_games_survive_user_id_migration(pool: PgPool) {
+        migrate_until(&pool, BEFORE_USER_IDS).await;
+        sqlx::query(
+            "INSERT INTO users (id, telegram_id, username) VALUES
+            (1, 't1', 'alice'), (2, 't2', 'alice'), (3, 't3', 'bob'), (4, 't4', '42'), (42, 't42', 'zed')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        // Записанная партия и партия, что была только в game_histories
+        sqlx::query(
+            "INSERT INTO game_records (id, seats, seed, variant, rated, finished_at, end_reason, winner_team, team_a_eyes, team_b_eyes)
+            VALUES ('recorded', ARRAY['alice', 'bob', '42', 'zed'], 7, 'classic', false, now(), 'finished', 1, 12, 4)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO game_rounds (game_id, round, trump, trump_team, team_a_points, team_b_points, eyes)
+            VALUES ('recorded', 0, 'Clubs', 1, 2, 0, 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO game_plays (game_id, round, seq, position, rank, suit)
+            VALUES ('recorded', 0, 0, 'North', 'Ace', 'Hearts'), ('recorded', 0, 1, 'East', 'Seven', 'Hearts')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r#"INSERT INTO game_histories (game_id, players, rated, winner_team, deals)
+            VALUES ('history', ARRAY['zed', '42', 'bob', 'alice'], true, 2,
+                '[{"trump": "Spades", "hands": {"North": [{"suit": "Clubs", "rank": "Jack"}]}, "plays": [["North", {"suit": "Clubs", "rank": "Jack"}]]}]')"#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let recorded = load_game_history(&pool, "recorded").await.unwrap().unwrap();
+        assert_eq!(recorded.players, ["1", "3", "4", "42"]);
+        assert_eq!(recorded.winner_team, 1);
+        let deal = &recorded.deals[0];
+        assert_eq!(deal.plays.len(), 2);
+        // Руки не хранились: восстановлены по сыгранным картам
+        assert_eq!(deal.hands[&PlayerPosition::East], [deal.plays[1].1]);
+
+        let history = load_game_history(&pool, "history").await.unwrap().unwrap();
+        assert_eq!(history.players, ["42", "4", "3", "1"]);
+        assert!(history.rated);
+        assert_eq!(history.deals[0].hands[&PlayerPosition::North].len(), 1);
+
+        let games = |uid: &'static str| {
+            let pool = pool.clone();
+            async move {
+                let mut ids: Vec<_> = list_games(&pool, uid, 10).await.unwrap().into_iter().map(|g| (g.game_id, g.won)).collect();
+                ids.sort();
+                ids
+            }
+        };
+        assert_eq!(games("4").await, [("history".to_string(), true), ("recorded".to_string(), true)]);
+        assert_eq!(games("42").await, [("history".to_string(), false), ("recorded".to_string(), false)]);
+        assert!(games("2").await.is_empty());
+    }
+}
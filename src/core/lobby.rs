@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use tokio::sync::Mutex;
+use tracing::info;
+use crate::ai::{BotKind, BotPlayer};
+use crate::core::manager::{GameManager, GameRoom, PlayerSession, Seat};
+use crate::utils::schemas::{PlayerPosition, SeatInfo, WSEvent};
+
+/// Invite codes avoid characters that are easy to confuse when typed.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 6;
+/// Lobbies that never start are dropped after this long.
+const LOBBY_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// A private table being assembled before the game starts.
+#[derive(Debug)]
+pub struct PrivateLobby {
+    pub code: String,
+    pub host: String,
+    pub seats: HashMap<PlayerPosition, Arc<Mutex<PlayerSession>>>,
+    pub created_at: Instant,
+}
+
+impl PrivateLobby {
+    async fn seat_info(&self) -> HashMap<PlayerPosition, SeatInfo> {
+        let mut seats = HashMap::new();
+        for (pos, player) in &self.seats {
+            let player = player.lock().await;
+            seats.insert(*pos, SeatInfo { id: player.id.clone(), name: player.name.clone(), is_bot: player.is_bot });
+        }
+        seats
+    }
+
+    async fn broadcast_update(&self) {
+        let event = WSEvent::LobbyUpdated {
+            code: self.code.clone(),
+            host: self.host.clone(),
+            seats: self.seat_info().await,
+        };
+        for player in self.seats.values() {
+            let _ = player.lock().await.sender.send(event.clone());
+        }
+    }
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LEN)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+impl GameManager {
+    /// Creates an empty private lobby owned by `host` and returns its code.
+    pub async fn create_private_room(&self, host: &str) -> String {
+        let mut lobbies = self.private_lobbies.lock().await;
+        let mut code = generate_code();
+        while lobbies.contains_key(&code) {
+            code = generate_code();
+        }
+        lobbies.insert(code.clone(), PrivateLobby {
+            code: code.clone(),
+            host: host.to_string(),
+            seats: HashMap::new(),
+            created_at: Instant::now(),
+        });
+        info!("Private room {code} created by {host}");
+        code
+    }
+
+    pub async fn find_lobby_seat(&self, uid: &str) -> Option<(String, Arc<Mutex<PlayerSession>>)> {
+        let lobbies = self.private_lobbies.lock().await;
+        for lobby in lobbies.values() {
+            for player in lobby.seats.values() {
+                if player.lock().await.id == uid {
+                    return Some((lobby.code.clone(), player.clone()));
+                }
+            }
+        }
+        None
+    }
+
+    /// Seats a player in a private lobby, moving them if they already sit
+    /// at another position of the same lobby.
+    pub async fn join_private_room(
+        &self,
+        code: &str,
+        pos: PlayerPosition,
+        player: Arc<Mutex<PlayerSession>>,
+    ) -> Result<(), &'static str> {
+        let uid = player.lock().await.id.clone();
+        if self.find_room_by_uid(&uid).await.is_some() {
+            return Err("Already in game");
+        }
+        if self.is_queued(&uid).await {
+            return Err("Already in queue");
+        }
+        if let Some((other, _)) = self.find_lobby_seat(&uid).await {
+            if other != code {
+                return Err("Already in another private room");
+            }
+        }
+
+        let mut lobbies = self.private_lobbies.lock().await;
+        let lobby = lobbies.get_mut(code).ok_or("Room not found")?;
+
+        if let Some(taken) = lobby.seats.get(&pos) {
+            if taken.lock().await.id != uid {
+                return Err("Seat is taken");
+            }
+        }
+
+        let mut current = None;
+        for (p, seated) in &lobby.seats {
+            if seated.lock().await.id == uid {
+                current = Some(*p);
+            }
+        }
+        if let Some(p) = current {
+            lobby.seats.remove(&p);
+        }
+        lobby.seats.insert(pos, player);
+        info!("Player {uid} took {pos:?} in private room {code}");
+        lobby.broadcast_update().await;
+        Ok(())
+    }
+
+    pub async fn leave_private_room(&self, uid: &str) {
+        let mut lobbies = self.private_lobbies.lock().await;
+        for lobby in lobbies.values_mut() {
+            let mut seat = None;
+            for (pos, player) in &lobby.seats {
+                if player.lock().await.id == uid {
+                    seat = Some(*pos);
+                }
+            }
+            if let Some(pos) = seat {
+                lobby.seats.remove(&pos);
+                lobby.broadcast_update().await;
+                info!("Player {uid} left private room {}", lobby.code);
+                return;
+            }
+        }
+    }
+
+    /// Starts the game of a private lobby once every seat is taken, or with
+    /// bots on the empty seats if the host asks for it. Only the host may
+    /// start, and private games are never rated.
+    pub async fn start_private_room(&self, code: &str, uid: &str, bots: Option<BotKind>) -> Result<(), &'static str> {
+        if self.is_shutting_down() {
+            return Err("Server is restarting");
+        }
+        let mut lobbies = self.private_lobbies.lock().await;
+        let lobby = lobbies.get(code).ok_or("Room not found")?;
+        if lobby.host != uid {
+            return Err("Only the host can start the game");
+        }
+        if lobby.seats.len() < 4 && bots.is_none() {
+            return Err("Not all seats are taken");
+        }
+        let lobby = lobbies.remove(code).expect("lobby checked above");
+        drop(lobbies);
+
+        let mut seats: HashMap<PlayerPosition, Seat> = lobby.seats.into_iter()
+            .map(|(pos, player)| (pos, Seat::Human(player)))
+            .collect();
+        if let Some(kind) = bots {
+            for pos in [PlayerPosition::North, PlayerPosition::East, PlayerPosition::South, PlayerPosition::West] {
+                seats.entry(pos).or_insert_with(|| Seat::Bot(BotPlayer::new(kind)));
+            }
+        }
+        let room = Arc::new(GameRoom::new(seats, false));
+        self.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+        info!("Private room {code} started as {}", room.id);
+        room.start().await;
+        Ok(())
+    }
+
+    pub async fn expire_private_rooms(&self) {
+        let mut lobbies = self.private_lobbies.lock().await;
+        let now = Instant::now();
+        let expired: Vec<String> = lobbies.values()
+            .filter(|l| now.duration_since(l.created_at) > LOBBY_TTL)
+            .map(|l| l.code.clone())
+            .collect();
+        for code in expired {
+            if let Some(lobby) = lobbies.remove(&code) {
+                for player in lobby.seats.values() {
+                    let _ = player.lock().await.sender.send(WSEvent::GameClose {
+                        reason: "Private room expired".to_string(),
+                    });
+                }
+                info!("Private room {code} expired");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+    /// A manager whose database is never reached by lobby code.
+    fn manager() -> GameManager {
+        GameManager::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap())
+    }
+
+    fn player(id: &str) -> (Arc<Mutex<PlayerSession>>, UnboundedReceiver<WSEvent>) {
+        let (sender, receiver) = unbounded_channel();
+        (PlayerSession::new(id.to_string(), id.to_string(), false, sender), receiver)
+    }
+
+    async fn seat_ids(manager: &GameManager, code: &str) -> HashMap<PlayerPosition, String> {
+        let lobbies = manager.private_lobbies.lock().await;
+        let mut ids = HashMap::new();
+        for (pos, player) in &lobbies[code].seats {
+            ids.insert(*pos, player.lock().await.id.clone());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn players_pick_and_change_seats() {
+        let manager = manager();
+        let code = manager.create_private_room("host").await;
+        assert_eq!(code.len(), CODE_LEN);
+        assert!(code.bytes().all(|c| CODE_ALPHABET.contains(&c)));
+
+        let (host, mut host_events) = player("host");
+        let (guest, _) = player("guest");
+        manager.join_private_room(&code, PlayerPosition::North, host.clone()).await.unwrap();
+        assert!(matches!(host_events.try_recv(), Ok(WSEvent::LobbyUpdated { .. })));
+
+        assert_eq!(manager.join_private_room(&code, PlayerPosition::North, guest.clone()).await, Err("Seat is taken"));
+        manager.join_private_room(&code, PlayerPosition::East, guest.clone()).await.unwrap();
+        // Переход на другое место освобождает прежнее
+        manager.join_private_room(&code, PlayerPosition::South, host.clone()).await.unwrap();
+        assert_eq!(seat_ids(&manager, &code).await, HashMap::from([
+            (PlayerPosition::South, "host".to_string()),
+            (PlayerPosition::East, "guest".to_string()),
+        ]));
+
+        manager.leave_private_room("guest").await;
+        assert_eq!(seat_ids(&manager, &code).await.len(), 1);
+        assert_eq!(manager.join_private_room("NOSUCH", PlayerPosition::North, guest).await, Err("Room not found"));
+    }
+
+    #[tokio::test]
+    async fn one_lobby_at_a_time_and_only_the_host_starts() {
+        let manager = manager();
+        let first = manager.create_private_room("host").await;
+        let second = manager.create_private_room("other").await;
+        let (host, _) = player("host");
+        manager.join_private_room(&first, PlayerPosition::North, host.clone()).await.unwrap();
+        assert_eq!(
+            manager.join_private_room(&second, PlayerPosition::North, host).await,
+            Err("Already in another private room")
+        );
+
+        assert_eq!(manager.start_private_room(&first, "guest", None).await, Err("Only the host can start the game"));
+        assert_eq!(manager.start_private_room(&first, "host", None).await, Err("Not all seats are taken"));
+        assert!(manager.private_lobbies.lock().await.contains_key(&first));
+    }
+}
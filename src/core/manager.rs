@@ -1,20 +1,45 @@
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use tokio::sync::Mutex;
-use once_cell::sync::Lazy;
+use tokio::sync::{Mutex, Notify};
+use sqlx::PgPool;
 use uuid::Uuid;
-use crate::utils::schemas::{GameState, PlayerPosition, Suit, WSEvent};
+use crate::ai::{BotKind, BotPlayer, PlayerView};
+use crate::core::context::get_global_context;
+use crate::core::lobby::PrivateLobby;
+use crate::core::rating::{record_game_result, GameResult};
+use crate::core::records::{seat_ids, GameEnd, GameRecord, GameRecorder};
+use crate::core::recovery::{delete_checkpoint, CHECKPOINT_EVERY};
+use crate::core::rematch::Series;
+use crate::core::resign::AbortVote;
+use crate::utils::schemas::{Card, DealRecord, GameState, PlayerPosition, QueueKind, SeatInfo, Suit, WSCardPlayed, WSEvent, WSGameOver, WSTrickWon, WSYourHand, WSYourTurn};
 use tracing::{info, warn, error};
 use std::time::{Instant, Duration};
-use crate::core::context::get_global_context;
-use tokio::task;
+use tokio::task::{self, JoinHandle};
+use std::future::Future;
 use futures_util::FutureExt;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Eyes a team needs to win the game.
+pub const EYES_TO_WIN: u32 = 12;
+/// How long a party request waits for the partner to confirm.
+const PARTY_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Queue wait after which the missing seats of a table are given to bots.
+const QUEUE_BOT_FILL_AFTER: Duration = Duration::from_secs(60);
+/// Pause before a bot move so humans can follow the table.
+const BOT_MOVE_DELAY: Duration = Duration::from_millis(800);
+pub const ALL_POSITIONS: [PlayerPosition; 4] = [PlayerPosition::North, PlayerPosition::East, PlayerPosition::South, PlayerPosition::West];
 
 
 #[derive(Debug, Clone)]
 pub struct PlayerSession {
+    /// `users.id` as text.
     pub id: String,
+    /// Display name shown at the table.
+    pub name: String,
+    /// Bot account connected over the public API rather than a person.
+    pub is_bot: bool,
     pub sender: tokio::sync::mpsc::UnboundedSender<WSEvent>,
     pub is_connected: Arc<AtomicBool>,
     pub last_ping: Arc<Mutex<Instant>>,
@@ -23,10 +48,14 @@ pub struct PlayerSession {
 impl PlayerSession{
     pub fn new(
         id: String,
+        name: String,
+        is_bot: bool,
         sender: tokio::sync::mpsc::UnboundedSender<WSEvent>
     ) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Self{
             id,
+            name,
+            is_bot,
             sender,
             is_connected: Arc::new(AtomicBool::new(true)),
             last_ping: Arc::new(Mutex::new(Instant::now()))
@@ -47,62 +76,456 @@ impl PlayerSession{
 
 
 
+/// Who occupies a position at the table.
+#[derive(Debug, Clone)]
+pub enum Seat {
+    Human(Arc<Mutex<PlayerSession>>),
+    Bot(BotPlayer),
+}
+
+impl Seat {
+    pub fn session(&self) -> Option<&Arc<Mutex<PlayerSession>>> {
+        match self {
+            Seat::Human(player) => Some(player),
+            Seat::Bot(_) => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GameRoom {
     pub id: String,
-    pub players: HashMap<PlayerPosition, Arc<Mutex<PlayerSession>>>,
+    pub players: HashMap<PlayerPosition, Seat>,
     pub state: Arc<Mutex<GameState>>,
+    /// Whether the result of this room affects player ratings.
+    pub rated: bool,
+    /// Seed of the first deal; deal `n` is dealt from `seed + n`.
+    pub seed: u64,
+    /// Set once the game is over and the rematch window is open.
+    pub finished_at: Mutex<Option<Instant>>,
+    /// Accepted rematch offers and whether each seat wants to swap partners.
+    pub rematch_votes: Mutex<HashMap<PlayerPosition, bool>>,
+    pub series: Mutex<Series>,
+    /// Seat that proposed a team resignation its partner has not answered yet.
+    pub resign_offer: Mutex<Option<PlayerPosition>>,
+    /// The abort vote in progress, if any.
+    pub abort_vote: Mutex<Option<AbortVote>>,
+    /// Bots standing in for disconnected humans until they come back.
+    pub autopilot: Mutex<HashMap<PlayerPosition, BotPlayer>>,
+    /// Deals completed so far, kept for the post-game analysis.
+    pub history: Mutex<Vec<DealRecord>>,
+    /// Hints used by each seat and when the last one was given.
+    pub hints: Mutex<HashMap<PlayerPosition, (u32, Option<Instant>)>>,
+    /// Everything broadcast to the table since the current deal began,
+    /// kept with the checkpoints.
+    pub events: Mutex<Vec<WSEvent>>,
+    bot_wakeup: Notify,
+    closed: AtomicBool,
+    /// Set by every play, cleared when the room is checkpointed.
+    changed: AtomicBool,
 }
 
 impl GameRoom {
-    pub fn new(players: HashMap<PlayerPosition, Arc<Mutex<PlayerSession>>>) -> Self {
-        let state = GameState::new(Suit::Hearts);
+    pub fn new(players: HashMap<PlayerPosition, Seat>, rated: bool) -> Self {
+        let seed = rand::random();
+        let state = GameState::new_with(Suit::Hearts, &mut StdRng::seed_from_u64(seed));
+        GameRoom::with_state(Uuid::new_v4().to_string(), players, rated, seed, state)
+    }
+
+    /// A room around an existing game, as when it is restored.
+    pub fn with_state(id: String, players: HashMap<PlayerPosition, Seat>, rated: bool, seed: u64, state: GameState) -> Self {
         Self {
-            id: Uuid::new_v4().to_string(),
+            id,
             players,
             state: Arc::new(Mutex::new(state)),
+            rated,
+            seed,
+            finished_at: Mutex::new(None),
+            rematch_votes: Mutex::new(HashMap::new()),
+            series: Mutex::new(Series::default()),
+            resign_offer: Mutex::new(None),
+            abort_vote: Mutex::new(None),
+            autopilot: Mutex::new(HashMap::new()),
+            history: Mutex::new(vec![]),
+            hints: Mutex::new(HashMap::new()),
+            events: Mutex::new(vec![]),
+            bot_wakeup: Notify::new(),
+            closed: AtomicBool::new(false),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Human seats of the room.
+    pub fn sessions(&self) -> impl Iterator<Item = (&PlayerPosition, &Arc<Mutex<PlayerSession>>)> {
+        self.players.iter().filter_map(|(pos, seat)| seat.session().map(|s| (pos, s)))
+    }
+
+    pub async fn broadcast(&self, event: WSEvent) {
+        self.events.lock().await.push(event.clone());
+        for (_, player) in self.sessions() {
+            let _ = player.lock().await.sender.send(event.clone());
+        }
+    }
+
+    pub async fn send_to(&self, pos: PlayerPosition, event: WSEvent) {
+        if let Some(player) = self.players.get(&pos).and_then(Seat::session) {
+            let _ = player.lock().await.sender.send(event);
+        }
+    }
+
+    pub async fn position_of(&self, uid: &str) -> Option<PlayerPosition> {
+        for (pos, player) in self.sessions() {
+            if player.lock().await.id == uid {
+                return Some(*pos);
+            }
+        }
+        None
+    }
+
+    /// Player id sitting at each position; bots are listed by their own id.
+    pub async fn lineup(&self) -> HashMap<PlayerPosition, String> {
+        let mut lineup = HashMap::new();
+        for (pos, seat) in &self.players {
+            let id = match seat {
+                Seat::Human(player) => player.lock().await.id.clone(),
+                Seat::Bot(bot) => bot.id.clone(),
+            };
+            lineup.insert(*pos, id);
+        }
+        lineup
+    }
+
+    pub async fn seat_info(&self) -> HashMap<PlayerPosition, SeatInfo> {
+        let mut seats = HashMap::new();
+        for (pos, seat) in &self.players {
+            let info = match seat {
+                Seat::Human(player) => {
+                    let player = player.lock().await;
+                    SeatInfo { id: player.id.clone(), name: player.name.clone(), is_bot: player.is_bot }
+                }
+                Seat::Bot(bot) => SeatInfo { id: bot.id.clone(), name: bot.name.clone(), is_bot: true },
+            };
+            seats.insert(*pos, info);
+        }
+        seats
+    }
+
+    /// Bot that moves for `pos`, either a bot seat or an autopilot.
+    pub async fn bot_for(&self, pos: PlayerPosition) -> Option<BotPlayer> {
+        match self.players.get(&pos)? {
+            Seat::Bot(bot) => Some(bot.clone()),
+            Seat::Human(_) => self.autopilot.lock().await.get(&pos).cloned(),
+        }
+    }
+
+    /// The bot or autopilot on move and what it sees. None once the game
+    /// is over or while a human is on move.
+    async fn bot_on_move(&self) -> Option<(PlayerPosition, BotPlayer, PlayerView)> {
+        if self.is_closed() || self.finished_at.lock().await.is_some() {
+            return None;
+        }
+        let state = self.state.lock().await;
+        let pos = state.current_turn;
+        let bot = self.bot_for(pos).await?;
+        let view = PlayerView::from_state(&state, pos);
+        (!view.legal.is_empty()).then_some((pos, bot, view))
+    }
+
+    /// Humans that currently take part in votes: bots and autopilots
+    /// always agree, so they are not waited for.
+    pub async fn human_voters(&self) -> usize {
+        let autopilot = self.autopilot.lock().await;
+        self.sessions().filter(|(pos, _)| !autopilot.contains_key(pos)).count()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.bot_wakeup.notify_one();
+    }
+
+    /// Lets the bot driver check whether a bot is on move.
+    pub fn wake_bots(&self) {
+        self.bot_wakeup.notify_one();
+    }
+
+    /// Whether the room changed since the last call.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+
+    /// Sends every seat its hand and tells the player on move to play.
+    pub async fn send_hands(&self, state: &GameState) {
+        for (pos, player) in self.sessions() {
+            let session = player.lock().await;
+            if let Some(hand) = state.hands.get(pos) {
+                let _ = session.sender.send(WSEvent::YourHand(WSYourHand {
+                    cards: hand.clone(),
+                }));
+                if state.current_turn == *pos {
+                    let _ = session.sender.send(WSEvent::YourTurn(WSYourTurn));
+                }
+            }
+        }
+    }
+
+    pub async fn start(self: &Arc<Self>) {
+        let lineup = self.lineup().await;
+        get_global_context().game_manager().records.record(GameRecord::Started {
+            game_id: self.id.clone(),
+            seats: seat_ids(&lineup),
+            seed: self.seed,
+            rated: self.rated,
+        });
+        for (pos, player) in self.sessions() {
+            let _ = player.lock().await.sender.send(WSEvent::GameStart {
+                room_id: self.id.clone(),
+                position: *pos,
+            });
+        }
+        self.broadcast(WSEvent::Seats { seats: self.seat_info().await }).await;
+        {
+            let state = self.state.lock().await;
+            self.send_hands(&state).await;
         }
+        self.resume();
     }
+
+    /// Lets the bots of the room play, without announcing a new game.
+    pub fn resume(self: &Arc<Self>) {
+        spawn_bot_driver(self.clone());
+        self.wake_bots();
+    }
+
+    /// Plays a card for `pos` and pushes the resulting events to the table.
+    /// Returns the winning team once the game is over.
+    pub async fn play_card(&self, pos: PlayerPosition, card: Card) -> Result<Option<u8>, &'static str> {
+        let mut state = self.state.lock().await;
+        state.play_card(pos, card)?;
+        self.changed.store(true, Ordering::SeqCst);
+        self.broadcast(WSEvent::CardPlayed(WSCardPlayed { position: pos, card })).await;
+
+        let Some(winner) = state.resolve_trick() else {
+            self.send_to(state.current_turn, WSEvent::YourTurn(WSYourTurn)).await;
+            return Ok(None);
+        };
+        self.broadcast(WSEvent::TrickWon(WSTrickWon { position: winner })).await;
+
+        if !state.hands.values().all(|h| h.is_empty()) {
+            self.send_to(winner, WSEvent::YourTurn(WSYourTurn)).await;
+            return Ok(None);
+        }
+
+        let round = {
+            let mut history = self.history.lock().await;
+            history.push(state.deal_record());
+            history.len()
+        };
+        self.broadcast(WSEvent::RoundSummary(state.round_summary())).await;
+        let eyes_before = state.team_eye.clone();
+        let eyes = state.update_eye_after_round().map(|team| {
+            (team, state.team_eye[&team] - eyes_before.get(&team).copied().unwrap_or(0))
+        });
+        get_global_context().game_manager().records.record(GameRecord::round(&self.id, round - 1, &state, eyes));
+        let team_a = state.team_eye.get(&1).copied().unwrap_or(0);
+        let team_b = state.team_eye.get(&2).copied().unwrap_or(0);
+        self.broadcast(WSEvent::EyeUpdated { team_a, team_b }).await;
+
+        if team_a >= EYES_TO_WIN || team_b >= EYES_TO_WIN {
+            self.broadcast(WSEvent::GameOver(WSGameOver {
+                scores: state.team_scores.clone(),
+            })).await;
+            return Ok(Some(if team_a >= EYES_TO_WIN { 1 } else { 2 }));
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(round as u64));
+        state.trump = Suit::random_suit_with(&mut rng); // TODO dont know!!!
+        state.update_hands_with(&mut rng);
+        // Прошлые сдачи уже в history, в чекпоинте храним только текущую
+        self.events.lock().await.clear();
+        self.broadcast(WSEvent::TrumpUpdated { trump: state.trump }).await;
+        self.send_hands(&state).await;
+        Ok(None)
+    }
+
+    /// Marks a human as gone and lets a bot play their cards until they
+    /// reconnect.
     pub async fn kick_player(&self, pos: PlayerPosition) {
-        if let Some(player) = self.players.get(&pos) {
-            let mut player_guard = player.lock().await;
+        let Some(player) = self.players.get(&pos).and_then(Seat::session) else {
+            return;
+        };
+        // Пометить как отключённого
+        player.lock().await.mark_as_disconnected();
 
-            // Пометить как отключённого
-            player_guard.mark_as_disconnected();
+        // Отправить сообщение об отключении другим
+        for (other_pos, other_player) in self.sessions() {
+            if *other_pos != pos {
+                let _ = other_player.lock().await.sender.send(WSEvent::PlayerDisconnected {
+                    position: pos,
+                });
+            }
+        }
 
-            // Отправить сообщение об отключении другим
-            for (other_pos, other_player) in &self.players {
-                if *other_pos != pos {
-                    let _ = other_player.lock().await.sender.send(WSEvent::PlayerDisconnected {
-                        position: pos,
-                    });
+        self.autopilot.lock().await.insert(pos, BotPlayer::new(BotKind::Heuristic));
+        self.broadcast(WSEvent::PlayerReplacedByBot { position: pos }).await;
+        self.wake_bots();
+    }
+
+    /// Gives a returning player their seat back and resends the table.
+    pub async fn reconnect(&self, pos: PlayerPosition) {
+        if self.autopilot.lock().await.remove(&pos).is_some() {
+            self.broadcast(WSEvent::PlayerReconnected { position: pos }).await;
+        }
+        let state = self.state.lock().await;
+        // Клиент мог перезапуститься вместе с сервером: присылаем стол целиком
+        self.send_to(pos, WSEvent::GameStart { room_id: self.id.clone(), position: pos }).await;
+        self.send_to(pos, WSEvent::Seats { seats: self.seat_info().await }).await;
+        self.send_to(pos, WSEvent::TrumpUpdated { trump: state.trump }).await;
+        self.send_to(pos, WSEvent::EyeUpdated {
+            team_a: state.team_eye.get(&1).copied().unwrap_or(0),
+            team_b: state.team_eye.get(&2).copied().unwrap_or(0),
+        }).await;
+        if let Some(hand) = state.hands.get(&pos) {
+            self.send_to(pos, WSEvent::YourHand(WSYourHand { cards: hand.clone() })).await;
+        }
+        if let Some(winner) = state.last_trick_winner() {
+            self.send_to(pos, WSEvent::LastTrick { winner, cards: state.last_trick.clone() }).await;
+        }
+        for (position, card) in &state.current_trick {
+            self.send_to(pos, WSEvent::CardPlayed(WSCardPlayed { position: *position, card: *card })).await;
+        }
+        if state.current_turn == pos {
+            self.send_to(pos, WSEvent::YourTurn(WSYourTurn)).await;
+        }
+    }
+}
+
+/// Plays for bot seats and autopilots whenever one of them is on move.
+/// Runs until the room is closed.
+fn spawn_bot_driver(room: Arc<GameRoom>) {
+    task::spawn(async move {
+        loop {
+            room.bot_wakeup.notified().await;
+            loop {
+                let Some((pos, _, _)) = room.bot_on_move().await else {
+                    break;
+                };
+                tokio::time::sleep(BOT_MOVE_DELAY).await;
+                // За время паузы партия могла закончиться, а игрок вернуться
+                let Some((now_on_move, bot, view)) = room.bot_on_move().await else {
+                    break;
+                };
+                if now_on_move != pos {
+                    continue;
+                }
+                let card = match task::spawn_blocking(move || bot.choose_card(&view)).await {
+                    Ok(card) => card,
+                    Err(e) => {
+                        error!("Room {}: bot at {pos:?} failed: {e:?}", room.id);
+                        break;
+                    }
+                };
+                let gm = get_global_context().game_manager();
+                if let Err(e) = gm.play_at(&room, pos, card).await {
+                    warn!("Room {}: bot move {card:?} at {pos:?} rejected: {e}", room.id);
+                    break;
                 }
             }
+            if room.is_closed() {
+                break;
+            }
         }
+    });
+}
+
+/// A unit waiting for a table: either a lone player or two partners.
+#[derive(Debug, Clone)]
+pub enum QueueEntry {
+    Solo(Arc<Mutex<PlayerSession>>),
+    Party([Arc<Mutex<PlayerSession>>; 2]),
+}
 
-        // TODO: тут ты можешь завершить игру, если это критично:
-        // например, если кто-то из игроков вышел — остановить партию.
+impl QueueEntry {
+    pub fn members(&self) -> &[Arc<Mutex<PlayerSession>>] {
+        match self {
+            QueueEntry::Solo(player) => std::slice::from_ref(player),
+            QueueEntry::Party(players) => players,
+        }
     }
 }
 
+/// A player who asked to search with a partner that has not confirmed yet.
+#[derive(Debug)]
+pub struct PendingParty {
+    pub partner: String,
+    pub queue: QueueKind,
+    pub session: Arc<Mutex<PlayerSession>>,
+    pub since: Instant,
+}
+
 #[derive(Debug)]
 pub struct GameManager {
-    pub waiting_queue: Mutex<VecDeque<Arc<Mutex<PlayerSession>>>>,
+    /// Entries of each public queue with the time they started waiting.
+    pub waiting_queue: Mutex<HashMap<QueueKind, VecDeque<(QueueEntry, Instant)>>>,
+    /// Keyed by the id of the player waiting for their partner.
+    pub pending_parties: Mutex<HashMap<String, PendingParty>>,
     pub active_rooms: Mutex<HashMap<String, Arc<GameRoom>>>,
+    /// Private lobbies by invite code; they never enter the public queue.
+    pub private_lobbies: Mutex<HashMap<String, PrivateLobby>>,
+    /// Background writer of game, round and play records.
+    pub records: GameRecorder,
+    /// Set once a shutdown started: no new games are accepted.
+    pub shutting_down: AtomicBool,
+    /// Result and checkpoint writes still running, awaited on shutdown.
+    writes: Mutex<Vec<JoinHandle<()>>>,
+    db: PgPool,
 }
 
 impl GameManager {
-    pub fn new() -> Self {
+    pub fn new(db: PgPool) -> Self {
         Self {
-            waiting_queue: Mutex::new(VecDeque::new()),
+            waiting_queue: Mutex::new(HashMap::new()),
+            pending_parties: Mutex::new(HashMap::new()),
             active_rooms: Mutex::new(HashMap::new()),
+            private_lobbies: Mutex::new(HashMap::new()),
+            records: GameRecorder::spawn(db.clone()),
+            shutting_down: AtomicBool::new(false),
+            writes: Mutex::new(vec![]),
+            db,
         }
     }
 
+    pub fn db(&self) -> &PgPool {
+        &self.db
+    }
+
+    /// Runs a database write in the background, keeping its handle for
+    /// `flush_writes`.
+    async fn spawn_write(&self, write: impl Future<Output = ()> + Send + 'static) {
+        let mut writes = self.writes.lock().await;
+        writes.retain(|write| !write.is_finished());
+        writes.push(task::spawn(write));
+    }
+
+    /// Waits for the background writes and game records made so far to
+    /// reach the database.
+    pub async fn flush_writes(&self) {
+        let writes = std::mem::take(&mut *self.writes.lock().await);
+        for write in writes {
+            if let Err(e) = write.await {
+                error!("Background write failed: {e:?}");
+            }
+        }
+        self.records.flush().await;
+    }
+
     pub async fn find_player_by_uid(&self, uid: &str) -> Option<Arc<Mutex<PlayerSession>>> {
         let rooms = self.active_rooms.lock().await;
         for room in rooms.values() {
-            for player in room.players.values() {
+            for (_, player) in room.sessions() {
                 let player_guard = player.lock().await;
                 if player_guard.id == uid {
                     return Some(player.clone());
@@ -112,49 +535,190 @@ impl GameManager {
         drop(rooms);
 
         let queue = self.waiting_queue.lock().await;
-        for player in queue.iter() {
+        for player in queue.values().flatten().flat_map(|(entry, _)| entry.members()) {
             let player_guard = player.lock().await;
             if player_guard.id == uid {
                 return Some(player.clone());
             }
         }
+        drop(queue);
+
+        if let Some(party) = self.pending_parties.lock().await.get(uid) {
+            return Some(party.session.clone());
+        }
+
+        self.find_lobby_seat(uid).await.map(|(_, player)| player)
+    }
 
+    pub async fn find_room_by_uid(&self, uid: &str) -> Option<(Arc<GameRoom>, PlayerPosition)> {
+        let rooms = self.active_rooms.lock().await;
+        for room in rooms.values() {
+            if let Some(pos) = room.position_of(uid).await {
+                return Some((room.clone(), pos));
+            }
+        }
         None
     }
 
+    /// Whether the player waits in the public queue, alone or as a party.
+    pub async fn is_queued(&self, uid: &str) -> bool {
+        let queue = self.waiting_queue.lock().await;
+        for player in queue.values().flatten().flat_map(|(entry, _)| entry.members()) {
+            if player.lock().await.id == uid {
+                return true;
+            }
+        }
+        drop(queue);
+        self.pending_parties.lock().await.contains_key(uid)
+    }
+
     pub async fn is_already_playing(&self, uid: &str) -> bool {
-        if let Some(_player) = self.find_player_by_uid(uid).await {
-            return true;
+        self.find_player_by_uid(uid).await.is_some()
+    }
+
+    /// Takes entries from the front of the queue until a full table is
+    /// assembled. Parties always get a whole team, so two parties or a party
+    /// and two solo players make a table; entries that do not fit wait.
+    pub async fn try_start_game(&self, kind: QueueKind) {
+        let mut queues = self.waiting_queue.lock().await;
+        let queue = queues.entry(kind).or_default();
+        let picked = pick_table(queue);
+        if picked.iter().map(|i| queue[*i].0.members().len()).sum::<usize>() < 4 {
+            return;
         }
-        return false;
+        let entries = take_entries(queue, &picked);
+        drop(queues);
+
+        let room = Arc::new(GameRoom::new(seat_entries(&entries), kind.is_rated()));
+        self.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+        room.start().await;
     }
 
-    pub async fn try_start_game(&self) {
-        let mut queue = self.waiting_queue.lock().await;
-        if queue.len() >= 4 {
-            let mut map = HashMap::new();
-            for (i, pos) in [PlayerPosition::North, PlayerPosition::East, PlayerPosition::South, PlayerPosition::West].iter().enumerate() {
-                if let Some(player) = queue.pop_front() {
-                    map.insert(*pos, player);
-                }
+    /// Seats players who waited too long for a full table together with
+    /// bots. Such games are not rated.
+    pub async fn fill_queue_with_bots(&self) {
+        let mut queues = self.waiting_queue.lock().await;
+        let Some(queue) = queues.values_mut()
+            .find(|q| q.front().is_some_and(|(_, since)| since.elapsed() >= QUEUE_BOT_FILL_AFTER))
+        else {
+            return;
+        };
+        let picked = pick_table(queue);
+        let entries = take_entries(queue, &picked);
+        drop(queues);
+
+        let room = Arc::new(GameRoom::new(seats_with_bots(&entries), false));
+        info!("Room {}: queue filled with bots", room.id);
+        self.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+        room.start().await;
+    }
+
+    /// Plays a card on behalf of `uid` in whatever room they are seated.
+    pub async fn play_card(&self, uid: &str, card: Card) -> Result<(), &'static str> {
+        let (room, pos) = self.find_room_by_uid(uid).await.ok_or("Not in game")?;
+        if room.autopilot.lock().await.contains_key(&pos) {
+            return Err("Seat is played by a bot");
+        }
+        self.play_at(&room, pos, card).await
+    }
+
+    /// Plays a card for whoever sits at `pos`, human or bot.
+    pub async fn play_at(&self, room: &GameRoom, pos: PlayerPosition, card: Card) -> Result<(), &'static str> {
+        if room.finished_at.lock().await.is_some() {
+            return Err("Game is over");
+        }
+        // После начала остановки новые сдачи не начинаются: комната ждёт рестарта в чекпоинте
+        if self.is_shutting_down() && room.state.lock().await.deal_plays.is_empty() {
+            return Err("Server is restarting");
+        }
+        match room.play_card(pos, card).await? {
+            // Боты не будятся: после конца партии им нечем ходить
+            Some(winner_team) => self.finish_game(room, winner_team).await,
+            None => room.wake_bots(),
+        }
+        Ok(())
+    }
+
+    /// Hands a reconnecting player their seat back from the autopilot.
+    pub async fn reconnect(&self, uid: &str) {
+        if let Some((room, pos)) = self.find_room_by_uid(uid).await {
+            room.reconnect(pos).await;
+            info!("Room {}: {uid} reconnected at {pos:?}", room.id);
+        }
+    }
+
+    /// Records the result of a finished room in the background and offers
+    /// the table a rematch.
+    pub async fn finish_game(&self, room: &GameRoom, winner_team: u8) {
+        let lineup = room.lineup().await;
+        room.series.lock().await.record(&lineup, winner_team);
+
+        let result = {
+            let state = room.state.lock().await;
+            self.record_unfinished_deal(room, &state).await;
+            GameResult {
+                room_id: room.id.clone(),
+                seed: room.seed,
+                rated: room.rated,
+                winner_team,
+                team_eye: state.team_eye.clone(),
+                lineup,
             }
-            let room = Arc::new(GameRoom::new(map));
-            self.active_rooms.lock().await.insert(room.id.clone(), room.clone());
-            for (pos, player) in &room.players {
-                let player = player.lock().await;
-                let _ = player.sender.send(WSEvent::GameStart {
-                    room_id: room.id.clone(),
-                    position: *pos,
-                });
+        };
+        self.records.record(GameRecord::Ended(result.end()));
+
+        if room.rated {
+            let db = self.db.clone();
+            self.spawn_write(async move {
+                if let Err(e) = record_game_result(&db, &result).await {
+                    error!("Room {}: failed to record game result: {e:?}", result.room_id);
+                }
+            }).await;
+        }
+
+        let db = self.db.clone();
+        let room_id = room.id.clone();
+        self.spawn_write(async move {
+            if let Err(e) = delete_checkpoint(&db, &room_id).await {
+                error!("Room {room_id}: failed to delete checkpoint: {e:?}");
             }
+        }).await;
+
+        self.offer_rematch(room).await;
+    }
+
+    /// Stores the deal in progress as the last round of a game that ends
+    /// before it is played out, by resignation, timeout or abort, so replays
+    /// and analysis see its cards too.
+    async fn record_unfinished_deal(&self, room: &GameRoom, state: &GameState) {
+        if state.deal_plays.is_empty() || state.hands.values().all(|h| h.is_empty()) {
+            return;
         }
+        let mut history = room.history.lock().await;
+        self.records.record(GameRecord::round(&room.id, history.len(), state, None));
+        history.push(state.deal_record());
     }
 
     pub async fn close_room(&self, room_id: &str, reason: &str) {
         let mut rooms_guard = self.active_rooms.lock().await;
 
         if let Some(room) = rooms_guard.remove(room_id) {
-            for player in room.players.values() {
+            room.close();
+            if room.finished_at.lock().await.is_none() {
+                let seats = seat_ids(&room.lineup().await);
+                let state = room.state.lock().await;
+                self.record_unfinished_deal(room.as_ref(), &state).await;
+                self.records.record(GameRecord::Ended(GameEnd {
+                    game_id: room.id.clone(),
+                    seats,
+                    seed: room.seed,
+                    rated: room.rated,
+                    reason: reason.to_string(),
+                    winner_team: None,
+                    team_eye: [state.team_eye[&1], state.team_eye[&2]],
+                }));
+            }
+            for (_, player) in room.sessions() {
                 let player_guard = player.lock().await;
                 let _ = player_guard.sender.send(
                     WSEvent::GameClose {
@@ -163,49 +727,77 @@ impl GameManager {
                 );
             }
 
-            tracing::info!("Room {room_id} closed: {reason}");
+            let db = self.db.clone();
+            let room_id = room_id.to_string();
+            self.spawn_write(async move {
+                if let Err(e) = delete_checkpoint(&db, &room_id).await {
+                    error!("Room {room_id}: failed to delete checkpoint: {e:?}");
+                }
+            }).await;
+
+            tracing::info!("Room {} closed: {reason}", room.id);
         }
     }
 
     pub fn start_monitoring(self: Arc<Self>) {
         task::spawn(async move {
+            let mut last_checkpoint = Instant::now();
             loop {
                 // Защита от паник
                 let result = std::panic::AssertUnwindSafe(async {
                     tokio::time::sleep(Duration::from_secs(5)).await;
+                    if last_checkpoint.elapsed() >= CHECKPOINT_EVERY {
+                        self.checkpoint_rooms().await;
+                        last_checkpoint = Instant::now();
+                    }
+                    self.expire_pending_parties().await;
+                    self.expire_private_rooms().await;
+                    self.expire_rematch_offers().await;
+                    self.expire_abort_votes().await;
+
+                    self.fill_queue_with_bots().await;
 
                     let now = Instant::now();
                     let mut to_kick = vec![];
+                    let mut to_close = vec![];
 
-                    let mut rooms_guard = self.active_rooms.lock().await;
-                    for (room_id, room) in rooms_guard.iter_mut() {
-                        for (pos, player) in room.players.iter() {
+                    let rooms_guard = self.active_rooms.lock().await;
+                    for (room_id, room) in rooms_guard.iter() {
+                        let finished = room.finished_at.lock().await.is_some();
+                        let autopilot = room.autopilot.lock().await.clone();
+                        let mut connected = 0;
+                        for (pos, player) in room.sessions() {
+                            if autopilot.contains_key(pos) {
+                                continue;
+                            }
                             let player_guard = player.lock().await;
                             let last_ping = player_guard.last_ping.lock().await;
                             let elapsed = now.duration_since(*last_ping);
 
                             if elapsed > Duration::from_secs(15) {
                                 info!("⏰ Kick: player {} (no ping for {:?})", player_guard.id, elapsed);
-                                to_kick.push((room_id.clone(), *pos));
+                                to_kick.push((room.clone(), *pos));
+                            } else {
+                                connected += 1;
                             }
                         }
+                        // Бот доигрывает за отключившегося, пока за столом есть люди
+                        if connected == 0 || (finished && to_kick.iter().any(|(r, _)| r.id == *room_id)) {
+                            to_close.push(room_id.clone());
+                        }
                     }
+                    drop(rooms_guard);
 
-                    for (room_id, pos) in &to_kick {
-                        if let Some(room) = rooms_guard.get_mut(room_id) {
+                    for (room, pos) in &to_kick {
+                        if !to_close.contains(&room.id) {
                             room.kick_player(*pos).await;
-                            info!("❌ Room {} — kicked player at position {:?}", room_id, pos);
+                            info!("🤖 Room {} — bot took over position {:?}", room.id, pos);
                         }
                     }
-                    drop(rooms_guard);
-                    for (room_id, pos) in &to_kick {
+                    for room_id in &to_close {
                         self.close_room(room_id.as_str(), "Timeout").await;
-                        info!("❌ Room {} closed {:?}", room_id, pos);
+                        info!("❌ Room {} closed", room_id);
                     }
-
-
-
-
                 }).catch_unwind().await;
 
                 if let Err(e) = result {
@@ -216,15 +808,45 @@ impl GameManager {
         });
     }
 
-    pub async fn join(&self, player: Arc<Mutex<PlayerSession>>) {
-        let player_id = {
+    /// Drops party requests whose partner never showed up.
+    async fn expire_pending_parties(&self) {
+        let mut pending = self.pending_parties.lock().await;
+        let now = Instant::now();
+        let expired: Vec<String> = pending.iter()
+            .filter(|(_, p)| now.duration_since(p.since) > PARTY_WAIT_TIMEOUT)
+            .map(|(uid, _)| uid.clone())
+            .collect();
+        for uid in expired {
+            if let Some(party) = pending.remove(&uid) {
+                let _ = party.session.lock().await.sender.send(WSEvent::Error {
+                    detail: format!("{} did not join the party", party.partner),
+                });
+                info!("Party request {uid} -> {} expired", party.partner);
+            }
+        }
+    }
+
+    pub async fn join(&self, player: Arc<Mutex<PlayerSession>>, partner: Option<String>, queue: Option<QueueKind>) {
+        let (player_id, is_bot) = {
             let player_guard = player.lock().await;
-            player_guard.id.clone()
+            (player_guard.id.clone(), player_guard.is_bot)
+        };
+
+        let kind = queue.unwrap_or(if is_bot { QueueKind::Bots } else { QueueKind::Rated });
+        let refusal = match kind {
+            _ if self.is_shutting_down() => Some("Server is restarting"),
+            QueueKind::Rated if is_bot => Some("Bot accounts can not join the rated queue"),
+            QueueKind::Bots if !is_bot => Some("Only bot accounts can join the bot queue"),
+            _ => None,
         };
+        if let Some(detail) = refusal {
+            let _ = player.lock().await.sender.send(WSEvent::Error { detail: detail.to_string() });
+            return;
+        }
 
         {
             let queue = self.waiting_queue.lock().await;
-            for p in queue.iter() {
+            for p in queue.values().flatten().flat_map(|(entry, _)| entry.members()) {
                 let p_id = p.lock().await.id.clone();
                 if p_id == player_id {
                     let _ = player.lock().await.sender.send(WSEvent::Error {
@@ -236,29 +858,283 @@ impl GameManager {
             }
         }
 
-        {
-            let rooms = self.active_rooms.lock().await;
-            for room in rooms.values() {
-                for p in room.players.values() {
-                    let p_id = p.lock().await.id.clone();
-                    if p_id == player_id {
-                        let _ = player.lock().await.sender.send(WSEvent::Error {
-                            detail: "Already in game".to_string(),
-                        });
-                        info!("Player {player_id} already in game");
-                        return;
-                    }
-                }
+        if let Some((room, _)) = self.find_room_by_uid(&player_id).await {
+            if room.finished_at.lock().await.is_none() {
+                let _ = player.lock().await.sender.send(WSEvent::Error {
+                    detail: "Already in game".to_string(),
+                });
+                info!("Player {player_id} already in game");
+                return;
             }
+            // Searching for a new game while the rematch offer is open is a refusal.
+            self.close_room(&room.id, "Rematch declined").await;
+        }
+
+        if self.find_lobby_seat(&player_id).await.is_some() {
+            let _ = player.lock().await.sender.send(WSEvent::Error {
+                detail: "Already in private room".to_string(),
+            });
+            info!("Player {player_id} already in private room");
+            return;
         }
+
+        let entry = match partner {
+            None => {
+                self.pending_parties.lock().await.remove(&player_id);
+                QueueEntry::Solo(player.clone())
+            }
+            Some(partner) if partner == player_id => {
+                let _ = player.lock().await.sender.send(WSEvent::Error {
+                    detail: "Can not party with yourself".to_string(),
+                });
+                return;
+            }
+            Some(partner) => {
+                let mut pending = self.pending_parties.lock().await;
+                let accepted = pending.get(&partner).is_some_and(|p| p.partner == player_id);
+                if accepted && pending[&partner].queue != kind {
+                    let _ = player.lock().await.sender.send(WSEvent::Error {
+                        detail: "Partner searches in another queue".to_string(),
+                    });
+                    return;
+                }
+                if !accepted {
+                    let _ = player.lock().await.sender.send(WSEvent::PartyWaiting {
+                        partner: partner.clone(),
+                    });
+                    info!("Player {player_id} waits for partner {partner}");
+                    pending.insert(player_id, PendingParty {
+                        partner,
+                        queue: kind,
+                        session: player.clone(),
+                        since: Instant::now(),
+                    });
+                    return;
+                }
+
+                pending.remove(&player_id);
+                let host = pending.remove(&partner).expect("pending party checked above");
+                drop(pending);
+
+                let _ = host.session.lock().await.sender.send(WSEvent::PartyFormed {
+                    partner: player_id.clone(),
+                });
+                let _ = player.lock().await.sender.send(WSEvent::PartyFormed {
+                    partner: partner.clone(),
+                });
+                info!("Party {partner} + {player_id} formed");
+                QueueEntry::Party([host.session, player.clone()])
+            }
+        };
+
         {
-            info!("Player {player_id} added to queue");
-            let mut queue = self.waiting_queue.lock().await;
-            queue.push_back(player.clone());
+            info!("Player {player_id} added to {kind:?} queue");
+            let mut queues = self.waiting_queue.lock().await;
+            queues.entry(kind).or_default().push_back((entry, Instant::now()));
+        }
+
+        self.try_start_game(kind).await;
+    }
+}
+
+/// Indices of the queue entries that make up the next table, front first.
+fn pick_table(queue: &VecDeque<(QueueEntry, Instant)>) -> Vec<usize> {
+    let mut picked = vec![];
+    let mut seats = 0;
+    for (i, (entry, _)) in queue.iter().enumerate() {
+        let size = entry.members().len();
+        if seats + size <= 4 {
+            picked.push(i);
+            seats += size;
+        }
+        if seats == 4 {
+            break;
         }
+    }
+    picked
+}
+
+fn take_entries(queue: &mut VecDeque<(QueueEntry, Instant)>, picked: &[usize]) -> Vec<QueueEntry> {
+    let mut entries: Vec<QueueEntry> = picked.iter().rev()
+        .filter_map(|i| queue.remove(*i))
+        .map(|(entry, _)| entry)
+        .collect();
+    entries.reverse();
+    entries
+}
+
+/// Parties get North/South first, then East/West; solos fill free seats.
+fn seat_entries(entries: &[QueueEntry]) -> HashMap<PlayerPosition, Seat> {
+    let mut map = HashMap::new();
+    let mut free = ALL_POSITIONS.to_vec();
+    for entry in entries {
+        if let QueueEntry::Party([first, second]) = entry {
+            let pos = if map.is_empty() { PlayerPosition::North } else { PlayerPosition::East };
+            map.insert(pos, Seat::Human(first.clone()));
+            map.insert(pos.next().next(), Seat::Human(second.clone()));
+            free.retain(|p| p.team() != pos.team());
+        }
+    }
+    for entry in entries {
+        if let QueueEntry::Solo(player) = entry {
+            map.insert(free.remove(0), Seat::Human(player.clone()));
+        }
+    }
+    map
+}
 
-        self.try_start_game().await;
+/// `seat_entries` with server bots in the seats left free.
+fn seats_with_bots(entries: &[QueueEntry]) -> HashMap<PlayerPosition, Seat> {
+    let mut seats = seat_entries(entries);
+    for pos in ALL_POSITIONS {
+        seats.entry(pos).or_insert_with(|| Seat::Bot(BotPlayer::new(BotKind::Heuristic)));
     }
+    seats
 }
 
-pub static GAME_MANAGER: Lazy<GameManager> = Lazy::new(|| GameManager::new());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, is_bot: bool) -> Arc<Mutex<PlayerSession>> {
+        let (sender, _) = tokio::sync::mpsc::unbounded_channel();
+        PlayerSession::new(id.to_string(), id.to_string(), is_bot, sender)
+    }
+
+    fn solo(id: &str) -> QueueEntry {
+        QueueEntry::Solo(session(id, false))
+    }
+
+    fn party(first: &str, second: &str) -> QueueEntry {
+        QueueEntry::Party([session(first, false), session(second, false)])
+    }
+
+    fn queue(entries: Vec<QueueEntry>) -> VecDeque<(QueueEntry, Instant)> {
+        entries.into_iter().map(|entry| (entry, Instant::now())).collect()
+    }
+
+    /// Who sits where: player ids, `bot` for server bots.
+    fn seated(seats: &HashMap<PlayerPosition, Seat>) -> Vec<String> {
+        ALL_POSITIONS
+            .iter()
+            .map(|pos| match &seats[pos] {
+                Seat::Human(player) => player.try_lock().unwrap().id.clone(),
+                Seat::Bot(_) => "bot".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partners_sit_opposite_each_other() {
+        let entries = [solo("a"), party("b", "c"), solo("d")];
+        assert_eq!(seated(&seat_entries(&entries)), ["b", "a", "c", "d"]);
+
+        let entries = [party("a", "b"), party("c", "d")];
+        assert_eq!(seated(&seat_entries(&entries)), ["a", "c", "b", "d"]);
+    }
+
+    #[test]
+    fn a_party_that_does_not_fit_waits_for_the_next_table() {
+        let waiting = queue(vec![solo("a"), solo("b"), solo("c"), party("d", "e"), solo("f")]);
+        assert_eq!(pick_table(&waiting), [0, 1, 2, 4]);
+
+        let mut waiting = queue(vec![party("a", "b"), solo("c"), party("d", "e"), solo("f")]);
+        let picked = pick_table(&waiting);
+        assert_eq!(picked, [0, 1, 3]);
+        let entries = take_entries(&mut waiting, &picked);
+        assert_eq!(seated(&seat_entries(&entries)), ["a", "c", "b", "f"]);
+        assert_eq!(waiting.len(), 1);
+        assert!(matches!(waiting[0].0, QueueEntry::Party(_)));
+    }
+
+    #[test]
+    fn short_queue_picks_fewer_than_four_seats() {
+        let waiting = queue(vec![party("a", "b"), solo("c")]);
+        let seats: usize = pick_table(&waiting).iter().map(|i| waiting[*i].0.members().len()).sum();
+        assert_eq!(seats, 3);
+    }
+
+    #[test]
+    fn bots_fill_the_free_seats() {
+        assert_eq!(seated(&seats_with_bots(&[party("a", "b"), solo("c")])), ["a", "c", "b", "bot"]);
+        assert_eq!(seated(&seats_with_bots(&[solo("a")])), ["a", "bot", "bot", "bot"]);
+    }
+
+    #[test]
+    fn bot_accounts_are_seated_like_people() {
+        // Смешанная очередь: бот-аккаунты занимают места как люди
+        let entries = [
+            QueueEntry::Solo(session("bot:alpha", true)),
+            party("a", "b"),
+            QueueEntry::Party([session("bot:beta", true), session("bot:gamma", true)]),
+        ];
+        let waiting = queue(entries.to_vec());
+        assert_eq!(pick_table(&waiting), [0, 1]);
+        assert_eq!(seated(&seats_with_bots(&entries[..2])), ["a", "bot:alpha", "b", "bot"]);
+        assert_eq!(seated(&seat_entries(&entries[1..])), ["a", "bot:beta", "b", "bot:gamma"]);
+    }
+
+    #[tokio::test]
+    async fn bots_stop_moving_once_the_game_is_over() {
+        let mut seats: HashMap<PlayerPosition, Seat> = ALL_POSITIONS.into_iter()
+            .map(|pos| (pos, Seat::Bot(BotPlayer::new(BotKind::Heuristic))))
+            .collect();
+        seats.insert(PlayerPosition::East, Seat::Human(session("e", false)));
+        let room = GameRoom::new(seats, false);
+
+        let (pos, _, view) = room.bot_on_move().await.unwrap();
+        assert_eq!(pos, PlayerPosition::North);
+        assert_eq!(view.legal.len(), 8);
+        room.state.lock().await.current_turn = PlayerPosition::East;
+        assert!(room.bot_on_move().await.is_none());
+
+        // Последняя взятка сыграна: рук нет, ход формально у победителя
+        room.state.lock().await.current_turn = PlayerPosition::North;
+        room.state.lock().await.hands.values_mut().for_each(Vec::clear);
+        assert!(room.bot_on_move().await.is_none());
+
+        let room = GameRoom::new(ALL_POSITIONS.into_iter().map(|pos| (pos, Seat::Bot(BotPlayer::new(BotKind::Random)))).collect(), false);
+        *room.finished_at.lock().await = Some(Instant::now());
+        assert!(room.bot_on_move().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_closed_room_keeps_its_unfinished_deal() {
+        let manager = GameManager::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let room = Arc::new(GameRoom::new(ALL_POSITIONS.into_iter().map(|pos| (pos, Seat::Bot(BotPlayer::new(BotKind::Random)))).collect(), false));
+        for _ in 0..3 {
+            let (pos, card) = {
+                let state = room.state.lock().await;
+                (state.current_turn, state.legal_cards(state.current_turn)[0])
+            };
+            room.play_card(pos, card).await.unwrap();
+        }
+        manager.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+
+        manager.close_room(&room.id, "Timeout").await;
+        let history = room.history.lock().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].plays.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn no_new_deal_starts_once_shutdown_began() {
+        let manager = GameManager::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let room = GameRoom::new(ALL_POSITIONS.into_iter().map(|pos| (pos, Seat::Bot(BotPlayer::new(BotKind::Random)))).collect(), false);
+        let next_card = || async {
+            let state = room.state.lock().await;
+            (state.current_turn, state.legal_cards(state.current_turn)[0])
+        };
+
+        manager.shutting_down.store(true, Ordering::SeqCst);
+        let (pos, card) = next_card().await;
+        assert_eq!(manager.play_at(&room, pos, card).await, Err("Server is restarting"));
+
+        // Начатая до остановки сдача доигрывается
+        room.play_card(pos, card).await.unwrap();
+        let (pos, card) = next_card().await;
+        manager.play_at(&room, pos, card).await.unwrap();
+        assert_eq!(room.state.lock().await.deal_plays.len(), 2);
+        manager.flush_writes().await;
+    }
+}
@@ -1,4 +1,13 @@
 pub mod context;
+pub mod hint;
+pub mod history;
+pub mod lobby;
 pub mod manager;
+pub mod rating;
+pub mod records;
+pub mod recovery;
+pub mod rematch;
+pub mod resign;
+pub mod shutdown;
 // pub mod pool;
 // pub mod engine;
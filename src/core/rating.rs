@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use sqlx::PgPool;
+use tracing::{info, warn};
+use crate::core::records::{save_game_end, seat_ids, GameEnd};
+use crate::utils::schemas::PlayerPosition;
+
+/// Glicko-2 scale factor between the public rating scale and the internal one.
+const SCALE: f64 = 173.7178;
+const DEFAULT_RATING: f64 = 1500.0;
+/// Deviation never drops below this, so ratings keep moving for regulars.
+const MIN_DEVIATION: f64 = 30.0;
+const MAX_DEVIATION: f64 = 350.0;
+/// System constant constraining volatility changes.
+const TAU: f64 = 0.5;
+const EPSILON: f64 = 0.000001;
+/// Eye margin at which a win counts as a full 1.0 score.
+const MAX_EYE_MARGIN: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: DEFAULT_RATING,
+            deviation: MAX_DEVIATION,
+            volatility: 0.06,
+        }
+    }
+}
+
+impl Rating {
+    fn mu(&self) -> f64 {
+        (self.rating - DEFAULT_RATING) / SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / SCALE
+    }
+}
+
+/// Final result of a game as seen by the rating system.
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    pub room_id: String,
+    pub seed: u64,
+    pub rated: bool,
+    pub winner_team: u8,
+    pub team_eye: HashMap<u8, u32>,
+    /// Player id sitting at each position.
+    pub lineup: HashMap<PlayerPosition, String>,
+}
+
+impl GameResult {
+    /// The game's row in `game_records`.
+    pub fn end(&self) -> GameEnd {
+        GameEnd {
+            game_id: self.room_id.clone(),
+            seats: seat_ids(&self.lineup),
+            seed: self.seed,
+            rated: self.rated,
+            reason: "finished".to_string(),
+            winner_team: Some(self.winner_team),
+            team_eye: [
+                self.team_eye.get(&1).copied().unwrap_or(0),
+                self.team_eye.get(&2).copied().unwrap_or(0),
+            ],
+        }
+    }
+
+    pub fn eye_margin(&self) -> u32 {
+        let a = self.team_eye.get(&1).copied().unwrap_or(0);
+        let b = self.team_eye.get(&2).copied().unwrap_or(0);
+        a.abs_diff(b)
+    }
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected(mu: f64, mu_opp: f64, phi_opp: f64) -> f64 {
+    1.0 / (1.0 + (-g(phi_opp) * (mu - mu_opp)).exp())
+}
+
+/// Game score in `[0, 1]` for the winning side: a narrow win is worth
+/// noticeably more than a draw, a whitewash is a full point.
+pub fn margin_score(eye_margin: u32) -> f64 {
+    let margin = eye_margin.min(MAX_EYE_MARGIN) as f64 / MAX_EYE_MARGIN as f64;
+    0.75 + 0.25 * margin
+}
+
+fn new_volatility(sigma: f64, phi: f64, v: f64, delta: f64) -> f64 {
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let d = phi * phi + v + ex;
+        ex * (delta * delta - d) / (2.0 * d * d) - (x - a) / (TAU * TAU)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = c;
+        f_b = f_c;
+    }
+
+    (big_a / 2.0).exp()
+}
+
+/// Glicko-2 update of one player after a single team game.
+///
+/// The player's side is represented by the mean strength of the player and
+/// their partner, the opponents by their mean strength and pooled deviation.
+/// Only the player's own deviation drives the step size, so fresh accounts
+/// converge quickly while established partners barely move.
+pub fn update_player(player: Rating, partner: Rating, opponents: [Rating; 2], score: f64) -> Rating {
+    let mu_team = (player.mu() + partner.mu()) / 2.0;
+    let mu_opp = (opponents[0].mu() + opponents[1].mu()) / 2.0;
+    let phi_opp = ((opponents[0].phi().powi(2) + opponents[1].phi().powi(2)) / 2.0).sqrt();
+    step(player, mu_team, &[(mu_opp, phi_opp, score)])
+}
+
+/// Plain Glicko-2 rating period: `games` are opponents with the score
+/// against each.
+pub fn glicko2_update(player: Rating, games: &[(Rating, f64)]) -> Rating {
+    let games: Vec<(f64, f64, f64)> = games.iter().map(|(opp, score)| (opp.mu(), opp.phi(), *score)).collect();
+    step(player, player.mu(), &games)
+}
+
+/// Steps 3-8 of Glicko-2. Expected scores are computed for `mu_expected`
+/// (the team strength in a team game), the update is applied to `player`.
+fn step(player: Rating, mu_expected: f64, games: &[(f64, f64, f64)]) -> Rating {
+    let mu = player.mu();
+    let phi = player.phi();
+
+    let mut v_inv = 0.0;
+    let mut improvement = 0.0;
+    for (mu_opp, phi_opp, score) in games {
+        let g_opp = g(*phi_opp);
+        let e = expected(mu_expected, *mu_opp, *phi_opp);
+        v_inv += g_opp * g_opp * e * (1.0 - e);
+        improvement += g_opp * (score - e);
+    }
+    let v = 1.0 / v_inv;
+    let delta = v * improvement;
+
+    let sigma = new_volatility(player.volatility, phi, v, delta);
+    let phi_star = (phi * phi + sigma * sigma).sqrt();
+    let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi * new_phi * improvement;
+
+    Rating {
+        rating: new_mu * SCALE + DEFAULT_RATING,
+        deviation: (new_phi * SCALE).clamp(MIN_DEVIATION, MAX_DEVIATION),
+        volatility: sigma,
+    }
+}
+
+/// Computes new ratings for all four seats of a finished game.
+pub fn rate_game(ratings: &HashMap<PlayerPosition, Rating>, winner_team: u8, eye_margin: u32) -> HashMap<PlayerPosition, Rating> {
+    let win_score = margin_score(eye_margin);
+    let mut updated = HashMap::new();
+
+    for (pos, rating) in ratings {
+        let partner = ratings.get(&pos.next().next()).copied().unwrap_or_default();
+        let opponents = [
+            ratings.get(&pos.next()).copied().unwrap_or_default(),
+            ratings.get(&pos.next().next().next()).copied().unwrap_or_default(),
+        ];
+        let score = if pos.team() == winner_team { win_score } else { 1.0 - win_score };
+        updated.insert(*pos, update_player(*rating, partner, opponents, score));
+    }
+
+    updated
+}
+
+/// `users.id` of every seat, or the first seat that is not a user (a
+/// server bot), which makes the game unrated.
+pub fn rated_user_ids(lineup: &HashMap<PlayerPosition, String>) -> Result<HashMap<PlayerPosition, i32>, String> {
+    let mut ids = HashMap::new();
+    for (pos, uid) in lineup {
+        let id = uid.parse::<i32>().map_err(|_| uid.clone())?;
+        ids.insert(*pos, id);
+    }
+    if ids.len() != 4 {
+        return Err(format!("{} seats", ids.len()));
+    }
+    Ok(ids)
+}
+
+/// Stores the result of a game in `game_records` and the rating changes of
+/// its players, atomically. The recorder may be behind, so the result row
+/// is written here too rather than left to it.
+pub async fn record_game_result(pool: &PgPool, result: &GameResult) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    save_game_end(&mut *tx, &result.end()).await?;
+
+    // Сервисные боты сидят под своими id, в users их нет
+    let user_ids = match rated_user_ids(&result.lineup) {
+        Ok(ids) => ids,
+        Err(seat) => {
+            warn!("Room {}: {seat} is not a user, game is not rated", result.room_id);
+            return tx.commit().await;
+        }
+    };
+    let mut ratings = HashMap::new();
+    for (pos, user_id) in &user_ids {
+        let row = sqlx::query!(
+            "SELECT rating, rating_deviation, rating_volatility
+            FROM users WHERE id = $1
+            FOR UPDATE",
+            *user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        match row {
+            Some(row) => {
+                ratings.insert(*pos, Rating {
+                    rating: row.rating,
+                    deviation: row.rating_deviation,
+                    volatility: row.rating_volatility,
+                });
+            }
+            None => {
+                warn!("Room {}: unknown player {user_id}, game is not rated", result.room_id);
+                return tx.commit().await;
+            }
+        }
+    }
+
+    let updated = rate_game(&ratings, result.winner_team, result.eye_margin());
+
+    for (pos, new_rating) in &updated {
+        let user_id = user_ids[pos];
+        sqlx::query!(
+            "UPDATE users
+            SET rating = $1, rating_deviation = $2, rating_volatility = $3, games_played = games_played + 1
+            WHERE id = $4",
+            new_rating.rating,
+            new_rating.deviation,
+            new_rating.volatility,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO game_players (game_id, user_id, position, team, rating_before, rating_after)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+            result.room_id,
+            user_id,
+            format!("{pos:?}"),
+            pos.team() as i16,
+            ratings[pos].rating,
+            new_rating.rating
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    info!("Room {}: ratings updated for team {} win", result.room_id, result.winner_team);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::schemas::PlayerPosition::{East, North, South, West};
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating { rating, deviation, volatility: 0.06 }
+    }
+
+    /// Worked example from Glickman, "Example of the Glicko-2 system".
+    #[test]
+    fn glicko2_matches_reference_example() {
+        let player = rating(1500.0, 200.0);
+        let games = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+        let updated = glicko2_update(player, &games);
+        assert!((updated.rating - 1464.06).abs() < 0.01, "rating {}", updated.rating);
+        assert!((updated.deviation - 151.52).abs() < 0.01, "deviation {}", updated.deviation);
+        assert!((updated.volatility - 0.05999).abs() < 0.00001, "volatility {}", updated.volatility);
+    }
+
+    #[test]
+    fn team_game_moves_winners_up_and_losers_down() {
+        let ratings = HashMap::from([
+            (North, rating(1500.0, 350.0)),
+            (East, rating(1500.0, 350.0)),
+            (South, rating(1500.0, 350.0)),
+            (West, rating(1500.0, 350.0)),
+        ]);
+        let updated = rate_game(&ratings, North.team(), 4);
+        for pos in [North, South] {
+            assert!(updated[&pos].rating > 1500.0);
+        }
+        for pos in [East, West] {
+            assert!(updated[&pos].rating < 1500.0);
+        }
+        // Равные столы: выигрыш одних равен проигрышу других
+        let gain = updated[&North].rating - 1500.0;
+        let loss = 1500.0 - updated[&East].rating;
+        assert!((gain - loss).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bigger_margin_scores_more() {
+        assert_eq!(margin_score(MAX_EYE_MARGIN), 1.0);
+        assert_eq!(margin_score(MAX_EYE_MARGIN * 2), 1.0);
+        assert!(margin_score(1) < margin_score(6));
+        assert!(margin_score(0) > 0.5);
+    }
+
+    #[test]
+    fn deviation_stays_in_bounds() {
+        let veteran = rating(1800.0, MIN_DEVIATION);
+        let updated = update_player(veteran, veteran, [rating(1200.0, 30.0), rating(1200.0, 30.0)], 1.0);
+        assert!(updated.deviation >= MIN_DEVIATION);
+    }
+
+    #[test]
+    fn server_bots_make_the_game_unrated() {
+        let people = HashMap::from([
+            (North, "1".to_string()),
+            (East, "2".to_string()),
+            (South, "3".to_string()),
+            (West, "4".to_string()),
+        ]);
+        assert_eq!(rated_user_ids(&people).unwrap()[&South], 3);
+
+        let mut with_bot = people.clone();
+        with_bot.insert(West, "bot-heuristic-1".to_string());
+        assert_eq!(rated_user_ids(&with_bot), Err("bot-heuristic-1".to_string()));
+
+        let mut short = people;
+        short.remove(&East);
+        assert!(rated_user_ids(&short).is_err());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn result_is_stored_even_before_the_recorder_writes_the_game(pool: PgPool) {
+        sqlx::query("INSERT INTO users (id, telegram_id, username) VALUES (1, 't1', 'a'), (2, 't2', 'b'), (3, 't3', 'c'), (4, 't4', 'd')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let result = GameResult {
+            room_id: "game".to_string(),
+            seed: 7,
+            rated: true,
+            winner_team: 1,
+            team_eye: HashMap::from([(1, 12), (2, 3)]),
+            lineup: HashMap::from([
+                (North, "1".to_string()),
+                (East, "2".to_string()),
+                (South, "3".to_string()),
+                (West, "4".to_string()),
+            ]),
+        };
+        // Ни Started, ни Ended от записывающей задачи ещё не дошли
+        record_game_result(&pool, &result).await.unwrap();
+
+        let row: (Vec<String>, String, Option<i16>, i32) =
+            sqlx::query_as("SELECT seats, end_reason, winner_team, team_a_eyes FROM game_records WHERE id = 'game'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(row, (vec!["1".to_string(), "2".to_string(), "3".to_string(), "4".to_string()], "finished".to_string(), Some(1), 12));
+        let players: i64 = sqlx::query_scalar("SELECT count(*) FROM game_players WHERE game_id = 'game'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(players, 4);
+
+        // A late end of the same game changes nothing
+        save_game_end(&pool, &GameEnd { reason: "timeout".to_string(), winner_team: None, ..result.end() }).await.unwrap();
+        let reason: String = sqlx::query_scalar("SELECT end_reason FROM game_records WHERE id = 'game'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(reason, "finished");
+    }
+}
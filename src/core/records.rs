@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use sqlx::types::Json;
+use sqlx::{PgExecutor, PgPool};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::oneshot;
+use tracing::error;
+use crate::core::manager::ALL_POSITIONS;
+use crate::utils::schemas::{Card, GameState, PlayerPosition, Suit};
+
+/// Rule set a game is played under. There is only one so far.
+pub const VARIANT: &str = "classic";
+
+/// A step of a game to be stored in `game_records`, `game_rounds` and
+/// `game_plays`.
+#[derive(Debug, Clone)]
+pub enum GameRecord {
+    Started {
+        game_id: String,
+        /// Player or bot ids in `ALL_POSITIONS` order.
+        seats: Vec<String>,
+        seed: u64,
+        rated: bool,
+    },
+    Round {
+        game_id: String,
+        round: usize,
+        trump: Suit,
+        trump_team: u8,
+        points: [u32; 2],
+        /// Team that got eyes for the round and how many.
+        eyes: Option<(u8, u32)>,
+        /// Hands as dealt, for replays and analysis.
+        hands: HashMap<PlayerPosition, Vec<Card>>,
+        plays: Vec<(PlayerPosition, Card)>,
+    },
+    Ended(GameEnd),
+}
+
+/// The final row of a game in `game_records`. Carries the whole row, so it
+/// can be written even if the `Started` record has not been yet.
+#[derive(Debug, Clone)]
+pub struct GameEnd {
+    pub game_id: String,
+    /// Player or bot ids in `ALL_POSITIONS` order.
+    pub seats: Vec<String>,
+    pub seed: u64,
+    pub rated: bool,
+    pub reason: String,
+    /// `None` when the game was closed before anyone won.
+    pub winner_team: Option<u8>,
+    pub team_eye: [u32; 2],
+}
+
+/// Ids of `lineup` in `ALL_POSITIONS` order, as `game_records.seats` keeps them.
+pub fn seat_ids(lineup: &HashMap<PlayerPosition, String>) -> Vec<String> {
+    ALL_POSITIONS.iter().map(|pos| lineup.get(pos).cloned().unwrap_or_default()).collect()
+}
+
+impl GameRecord {
+    /// The current deal of `state` as round number `round`.
+    pub fn round(game_id: &str, round: usize, state: &GameState, eyes: Option<(u8, u32)>) -> Self {
+        GameRecord::Round {
+            game_id: game_id.to_string(),
+            round,
+            trump: state.trump,
+            trump_team: state.trump_team(),
+            points: [state.deal_points(1), state.deal_points(2)],
+            eyes,
+            hands: state.dealt_hands.clone(),
+            plays: state.deal_plays.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum RecorderMessage {
+    Record(GameRecord),
+    /// Answered once everything sent before it is written.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Writes game records in the background, in the order they were made, so
+/// rooms never wait for the database.
+#[derive(Debug, Clone)]
+pub struct GameRecorder {
+    sender: UnboundedSender<RecorderMessage>,
+}
+
+impl GameRecorder {
+    /// Starts the writer task; needs a running Tokio runtime.
+    pub fn spawn(db: PgPool) -> Self {
+        let (sender, mut receiver) = unbounded_channel::<RecorderMessage>();
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    RecorderMessage::Record(record) => {
+                        if let Err(e) = save_record(&db, &record).await {
+                            error!("Failed to save game record {record:?}: {e:?}");
+                        }
+                    }
+                    RecorderMessage::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    pub fn record(&self, record: GameRecord) {
+        if self.sender.send(RecorderMessage::Record(record)).is_err() {
+            error!("Game recorder is not running");
+        }
+    }
+
+    /// Waits until the records made so far are written.
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.sender.send(RecorderMessage::Flush(done)).is_err() || written.await.is_err() {
+            error!("Game recorder is not running");
+        }
+    }
+}
+
+async fn save_record(pool: &PgPool, record: &GameRecord) -> Result<(), sqlx::Error> {
+    match record {
+        GameRecord::Started { game_id, seats, seed, rated } => {
+            // Итог рейтинговой партии мог быть записан раньше
+            sqlx::query!(
+                "INSERT INTO game_records (id, seats, seed, variant, rated)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (id) DO NOTHING",
+                game_id,
+                seats,
+                *seed as i64,
+                VARIANT,
+                rated
+            )
+            .execute(pool)
+            .await?;
+        }
+        GameRecord::Round { game_id, round, trump, trump_team, points, eyes, hands, plays } => {
+            let mut tx = pool.begin().await?;
+            sqlx::query!(
+                "INSERT INTO game_rounds (game_id, round, trump, trump_team, team_a_points, team_b_points, eyes_team, eyes, hands)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                game_id,
+                *round as i16,
+                format!("{trump:?}"),
+                *trump_team as i16,
+                points[0] as i32,
+                points[1] as i32,
+                eyes.map(|(team, _)| team as i16),
+                eyes.map_or(0, |(_, eyes)| eyes as i32),
+                Json(hands) as _
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            let seqs: Vec<i16> = (0..plays.len() as i16).collect();
+            let positions: Vec<String> = plays.iter().map(|(pos, _)| format!("{pos:?}")).collect();
+            let ranks: Vec<String> = plays.iter().map(|(_, card)| format!("{:?}", card.rank)).collect();
+            let suits: Vec<String> = plays.iter().map(|(_, card)| format!("{:?}", card.suit)).collect();
+            sqlx::query!(
+                "INSERT INTO game_plays (game_id, round, seq, position, rank, suit)
+                SELECT $1, $2, * FROM UNNEST($3::SMALLINT[], $4::TEXT[], $5::TEXT[], $6::TEXT[])",
+                game_id,
+                *round as i16,
+                &seqs,
+                &positions,
+                &ranks,
+                &suits
+            )
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+        }
+        GameRecord::Ended(end) => save_game_end(pool, end).await?,
+    }
+    Ok(())
+}
+
+/// Writes how a game ended, creating its row if need be. A game ends once:
+/// later writes for the same game change nothing.
+pub async fn save_game_end(executor: impl PgExecutor<'_>, end: &GameEnd) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO game_records (id, seats, seed, variant, rated, finished_at, end_reason, winner_team, team_a_eyes, team_b_eyes)
+        VALUES ($1, $2, $3, $4, $5, now(), $6, $7, $8, $9)
+        ON CONFLICT (id) DO UPDATE
+        SET finished_at = EXCLUDED.finished_at, end_reason = EXCLUDED.end_reason, winner_team = EXCLUDED.winner_team,
+            team_a_eyes = EXCLUDED.team_a_eyes, team_b_eyes = EXCLUDED.team_b_eyes
+        WHERE game_records.finished_at IS NULL",
+        end.game_id,
+        &end.seats,
+        end.seed as i64,
+        VARIANT,
+        end.rated,
+        end.reason,
+        end.winner_team.map(|team| team as i16),
+        end.team_eye[0] as i32,
+        end.team_eye[1] as i32
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
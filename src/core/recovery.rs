@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+use crate::ai::{BotKind, BotPlayer};
+use crate::core::manager::{GameManager, GameRoom, PlayerSession, Seat, EYES_TO_WIN};
+use crate::utils::schemas::{DealRecord, GameState, PlayerPosition, WSEvent};
+
+/// How often rooms that changed are written to `room_checkpoints`.
+pub const CHECKPOINT_EVERY: Duration = Duration::from_secs(10);
+/// Format of `RoomSnapshot`s written now. Version 0 keyed human seats by
+/// username.
+pub const SNAPSHOT_VERSION: u32 = 1;
+/// Time restored players get to reconnect before bots take their seats.
+const RESTORE_GRACE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SeatSnapshot {
+    /// In version 0 snapshots `id` is the username and `name` is empty.
+    Human { id: String, #[serde(default)] name: String, is_bot: bool },
+    /// `kind` is a `BotKind` name.
+    Bot { id: String, kind: String },
+}
+
+/// Everything needed to bring a running room back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    /// `SNAPSHOT_VERSION` of the writer, 0 when absent.
+    #[serde(default)]
+    pub version: u32,
+    pub seats: HashMap<PlayerPosition, SeatSnapshot>,
+    pub state: GameState,
+    pub history: Vec<DealRecord>,
+    pub events: Vec<WSEvent>,
+}
+
+impl GameRoom {
+    /// Captures the room as of one moment. Locks are taken in the order
+    /// `play_card` takes them (state, history, events, then seats) and
+    /// held to the end, so no play lands halfway through.
+    pub async fn snapshot(&self) -> RoomSnapshot {
+        let state = self.state.lock().await;
+        let history = self.history.lock().await;
+        let events = self.events.lock().await;
+
+        let mut seats = HashMap::new();
+        for (pos, seat) in &self.players {
+            let snapshot = match seat {
+                Seat::Human(player) => {
+                    let player = player.lock().await;
+                    SeatSnapshot::Human { id: player.id.clone(), name: player.name.clone(), is_bot: player.is_bot }
+                }
+                Seat::Bot(bot) => SeatSnapshot::Bot { id: bot.id.clone(), kind: bot.kind.name() },
+            };
+            seats.insert(*pos, snapshot);
+        }
+        RoomSnapshot {
+            version: SNAPSHOT_VERSION,
+            seats,
+            state: state.clone(),
+            history: history.clone(),
+            events: events.clone(),
+        }
+    }
+
+    /// Rebuilds a room from its checkpoint. Humans come back disconnected
+    /// and take their seats again when they log in.
+    pub fn restore(id: String, rated: bool, seed: u64, snapshot: RoomSnapshot) -> Result<Self, String> {
+        let mut players = HashMap::new();
+        for (pos, seat) in snapshot.seats {
+            let seat = match seat {
+                SeatSnapshot::Human { id, name, is_bot } => {
+                    let (sender, _) = tokio::sync::mpsc::unbounded_channel();
+                    let name = if name.is_empty() { id.clone() } else { name };
+                    let session = PlayerSession::new(id, name, is_bot, sender);
+                    if let Ok(mut player) = session.try_lock() {
+                        player.mark_as_disconnected();
+                        // Пинг «из будущего»: таймаут начнёт считаться после паузы на переподключение
+                        player.last_ping = Arc::new(tokio::sync::Mutex::new(Instant::now() + RESTORE_GRACE));
+                    }
+                    Seat::Human(session)
+                }
+                SeatSnapshot::Bot { id, kind } => Seat::Bot(BotPlayer::with_id(kind.parse::<BotKind>()?, id)),
+            };
+            players.insert(pos, seat);
+        }
+
+        let mut room = GameRoom::with_state(id, players, rated, seed, snapshot.state);
+        *room.history.get_mut() = snapshot.history;
+        *room.events.get_mut() = snapshot.events;
+        Ok(room)
+    }
+}
+
+pub async fn save_checkpoint(pool: &PgPool, room: &GameRoom) -> Result<(), sqlx::Error> {
+    let snapshot = room.snapshot().await;
+    sqlx::query!(
+        "INSERT INTO room_checkpoints (room_id, rated, seed, snapshot)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (room_id) DO UPDATE
+        SET snapshot = EXCLUDED.snapshot, updated_at = now()",
+        room.id,
+        room.rated,
+        room.seed as i64,
+        Json(&snapshot) as _
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_checkpoint(pool: &PgPool, room_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM room_checkpoints WHERE room_id = $1", room_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+impl GameManager {
+    /// Writes every running room that changed since its last checkpoint.
+    pub async fn checkpoint_rooms(&self) {
+        let rooms: Vec<Arc<GameRoom>> = self.active_rooms.lock().await.values().cloned().collect();
+        for room in rooms {
+            if room.is_closed() || room.finished_at.lock().await.is_some() || !room.take_changed() {
+                continue;
+            }
+            if let Err(e) = save_checkpoint(self.db(), &room).await {
+                error!("Room {}: checkpoint failed: {e:?}", room.id);
+            }
+        }
+    }
+
+    /// Brings back the rooms that were running when the server stopped.
+    /// Called once on startup, before anyone can connect.
+    pub async fn restore_rooms(&self) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT room_id, rated, seed, snapshot AS "snapshot: Json<RoomSnapshot>"
+            FROM room_checkpoints"#
+        )
+        .fetch_all(self.db())
+        .await?;
+
+        let mut restored = 0;
+        for row in rows {
+            let mut snapshot = row.snapshot.0;
+            self.upgrade_legacy_seats(&mut snapshot).await?;
+            let over = snapshot.state.team_eye.values().any(|eyes| *eyes >= EYES_TO_WIN);
+            let room = match GameRoom::restore(row.room_id.clone(), row.rated, row.seed as u64, snapshot) {
+                Ok(room) if !over => Arc::new(room),
+                Ok(_) => {
+                    delete_checkpoint(self.db(), &row.room_id).await?;
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Room {}: can not be restored: {e}", row.room_id);
+                    delete_checkpoint(self.db(), &row.room_id).await?;
+                    continue;
+                }
+            };
+            self.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+            room.resume();
+            info!("Room {} restored", room.id);
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    /// Replaces usernames in seats of a version 0 checkpoint with the user
+    /// ids sessions are keyed by now.
+    async fn upgrade_legacy_seats(&self, snapshot: &mut RoomSnapshot) -> Result<(), sqlx::Error> {
+        if snapshot.version > 0 {
+            return Ok(());
+        }
+        for seat in snapshot.seats.values_mut() {
+            let SeatSnapshot::Human { id, name, .. } = seat else {
+                continue;
+            };
+            let user_id = sqlx::query_scalar!(
+                "SELECT id FROM users WHERE username = $1 ORDER BY id LIMIT 1",
+                id.as_str()
+            )
+            .fetch_optional(self.db())
+            .await?;
+            match user_id {
+                Some(user_id) => *name = std::mem::replace(id, user_id.to_string()),
+                None => warn!("Checkpoint seat {id} matches no user"),
+            }
+        }
+        snapshot.version = SNAPSHOT_VERSION;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::manager::ALL_POSITIONS;
+    use crate::utils::schemas::Suit;
+
+    fn human(id: &str, name: &str) -> SeatSnapshot {
+        SeatSnapshot::Human { id: id.to_string(), name: name.to_string(), is_bot: false }
+    }
+
+    fn seat_ids(snapshot: &RoomSnapshot) -> Vec<(String, String)> {
+        ALL_POSITIONS
+            .iter()
+            .filter_map(|pos| match &snapshot.seats[pos] {
+                SeatSnapshot::Human { id, name, .. } => Some((id.clone(), name.clone())),
+                SeatSnapshot::Bot { .. } => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn checkpoint_round_trip_restores_the_room() {
+        let (sender, _events) = tokio::sync::mpsc::unbounded_channel();
+        let mut seats: HashMap<PlayerPosition, Seat> = ALL_POSITIONS.into_iter()
+            .map(|pos| (pos, Seat::Bot(BotPlayer::new(BotKind::Heuristic))))
+            .collect();
+        seats.insert(PlayerPosition::South, Seat::Human(PlayerSession::new("7".to_string(), "Анна".to_string(), false, sender)));
+        let room = GameRoom::new(seats, true);
+        for _ in 0..5 {
+            let (pos, card) = {
+                let state = room.state.lock().await;
+                (state.current_turn, state.legal_cards(state.current_turn)[0])
+            };
+            room.play_card(pos, card).await.unwrap();
+        }
+        room.history.lock().await.push(room.state.lock().await.deal_record());
+
+        // Как в room_checkpoints: через JSON
+        let json = serde_json::to_string(&room.snapshot().await).unwrap();
+        let snapshot: RoomSnapshot = serde_json::from_str(&json).unwrap();
+        let restored = GameRoom::restore(room.id.clone(), room.rated, room.seed, snapshot).unwrap();
+
+        assert_eq!(restored.lineup().await, room.lineup().await);
+        let Seat::Bot(bot) = &restored.players[&PlayerPosition::North] else { panic!("bot seat lost") };
+        assert_eq!(bot.kind, BotKind::Heuristic);
+        let south = restored.players[&PlayerPosition::South].session().unwrap().lock().await;
+        assert_eq!((south.id.as_str(), south.name.as_str()), ("7", "Анна"));
+        assert!(!south.is_connected());
+        drop(south);
+
+        let state = |room: &GameRoom| serde_json::to_value(room.state.try_lock().unwrap().clone()).unwrap();
+        assert_eq!(state(&restored), state(&room));
+        assert_eq!(restored.state.lock().await.current_trick.len(), 1);
+        assert_eq!(restored.history.lock().await.len(), 1);
+        assert_eq!(restored.events.lock().await.len(), room.events.lock().await.len());
+        assert_eq!((restored.rated, restored.seed), (room.rated, room.seed));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn legacy_checkpoint_seats_become_user_ids(pool: PgPool) {
+        sqlx::query("INSERT INTO users (id, telegram_id, username) VALUES (4, 't4', '42'), (42, 't42', 'zed')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let manager = GameManager::new(pool);
+
+        let current = RoomSnapshot {
+            version: SNAPSHOT_VERSION,
+            seats: HashMap::from([
+                (PlayerPosition::North, human("42", "zed")),
+                (PlayerPosition::East, human("4", "42")),
+                (PlayerPosition::South, human("ghost", "ghost")),
+                (PlayerPosition::West, SeatSnapshot::Bot { id: "bot-1".to_string(), kind: BotKind::Heuristic.name() }),
+            ]),
+            state: GameState::new(Suit::Clubs),
+            history: vec![],
+            events: vec![],
+        };
+        // Чекпоинт, записанный до появления версии: в id имена, name пуст
+        let mut json = serde_json::to_value(&current).unwrap();
+        json.as_object_mut().unwrap().remove("version");
+        json["seats"]["North"] = serde_json::to_value(human("zed", "")).unwrap();
+        json["seats"]["East"] = serde_json::to_value(human("42", "")).unwrap();
+        json["seats"]["South"] = serde_json::to_value(human("ghost", "")).unwrap();
+        let mut legacy: RoomSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(legacy.version, 0);
+
+        manager.upgrade_legacy_seats(&mut legacy).await.unwrap();
+        assert_eq!(legacy.version, SNAPSHOT_VERSION);
+        assert_eq!(seat_ids(&legacy), [
+            ("42".to_string(), "zed".to_string()),
+            ("4".to_string(), "42".to_string()),
+            ("ghost".to_string(), String::new()),
+        ]);
+
+        // Текущие чекпоинты не трогаем, даже если id похож на имя
+        let mut unchanged = current.clone();
+        manager.upgrade_legacy_seats(&mut unchanged).await.unwrap();
+        assert_eq!(seat_ids(&unchanged), seat_ids(&current));
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::info;
+use crate::core::manager::{GameManager, GameRoom};
+use crate::utils::schemas::{PlayerPosition, WSEvent};
+
+/// How long the table has to accept a rematch after the game is over.
+pub const REMATCH_WINDOW: Duration = Duration::from_secs(30);
+
+/// Running score of the games the same four players played in a row.
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    pub games: u32,
+    /// Games won by each player id.
+    pub wins: HashMap<String, u32>,
+}
+
+impl Series {
+    pub fn record(&mut self, lineup: &HashMap<PlayerPosition, String>, winner_team: u8) {
+        self.games += 1;
+        for (pos, uid) in lineup {
+            let wins = self.wins.entry(uid.clone()).or_insert(0);
+            if pos.team() == winner_team {
+                *wins += 1;
+            }
+        }
+    }
+
+    pub fn event(&self, lineup: &HashMap<PlayerPosition, String>) -> WSEvent {
+        WSEvent::SeriesScore {
+            games: self.games,
+            wins: lineup.iter()
+                .map(|(pos, uid)| (*pos, self.wins.get(uid).copied().unwrap_or(0)))
+                .collect(),
+        }
+    }
+}
+
+/// New seat of a player when partners are swapped: North keeps its seat and
+/// the former East player becomes North's partner.
+fn swapped(pos: PlayerPosition) -> PlayerPosition {
+    match pos {
+        PlayerPosition::North => PlayerPosition::North,
+        PlayerPosition::East => PlayerPosition::South,
+        PlayerPosition::South => PlayerPosition::East,
+        PlayerPosition::West => PlayerPosition::West,
+    }
+}
+
+impl GameManager {
+    /// Opens the rematch window of a finished room.
+    pub async fn offer_rematch(&self, room: &GameRoom) {
+        *room.finished_at.lock().await = Some(Instant::now());
+        room.broadcast(WSEvent::RematchOffered {
+            seconds: REMATCH_WINDOW.as_secs(),
+        }).await;
+    }
+
+    /// Handles a rematch answer. A single refusal closes the room; once all
+    /// humans accepted a new room with the same seats is started, swapping
+    /// partners only if everyone asked for it.
+    pub async fn rematch(&self, uid: &str, accept: bool, swap_partners: bool) -> Result<(), &'static str> {
+        let (room, pos) = self.find_room_by_uid(uid).await.ok_or("Not in game")?;
+        if room.finished_at.lock().await.is_none() {
+            return Err("Game is not over");
+        }
+
+        if !accept {
+            info!("Room {}: {uid} declined the rematch", room.id);
+            self.close_room(&room.id, "Rematch declined").await;
+            return Ok(());
+        }
+
+        if self.is_shutting_down() {
+            return Err("Server is restarting");
+        }
+
+        let swap = {
+            let mut votes = room.rematch_votes.lock().await;
+            votes.insert(pos, swap_partners);
+            if votes.len() < room.human_voters().await {
+                None
+            } else {
+                Some(votes.values().all(|swap| *swap))
+            }
+        };
+        room.broadcast(WSEvent::RematchVote { position: pos }).await;
+
+        if let Some(swap) = swap {
+            self.start_rematch(&room, swap).await;
+        }
+        Ok(())
+    }
+
+    async fn start_rematch(&self, room: &GameRoom, swap: bool) {
+        let Some(old) = self.active_rooms.lock().await.remove(&room.id) else {
+            return;
+        };
+
+        old.close();
+
+        let players = old.players.iter()
+            .map(|(pos, seat)| (if swap { swapped(*pos) } else { *pos }, seat.clone()))
+            .collect();
+        let mut rematch = GameRoom::new(players, old.rated);
+        rematch.series = Mutex::new(old.series.lock().await.clone());
+        let rematch = Arc::new(rematch);
+
+        self.active_rooms.lock().await.insert(rematch.id.clone(), rematch.clone());
+        info!("Room {} rematched as {} (swap partners: {swap})", old.id, rematch.id);
+
+        rematch.start().await;
+        let lineup = rematch.lineup().await;
+        rematch.broadcast(rematch.series.lock().await.event(&lineup)).await;
+    }
+
+    /// Closes finished rooms whose rematch window ran out.
+    pub async fn expire_rematch_offers(&self) {
+        let now = Instant::now();
+        let mut expired = vec![];
+        for (room_id, room) in self.active_rooms.lock().await.iter() {
+            if let Some(finished_at) = *room.finished_at.lock().await {
+                if now.duration_since(finished_at) > REMATCH_WINDOW {
+                    expired.push(room_id.clone());
+                }
+            }
+        }
+        for room_id in expired {
+            self.close_room(&room_id, "GameOver").await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::PgPool;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use crate::ai::{BotKind, BotPlayer};
+    use crate::core::manager::{PlayerSession, Seat, ALL_POSITIONS};
+
+    fn lineup(ids: [&str; 4]) -> HashMap<PlayerPosition, String> {
+        ALL_POSITIONS.into_iter().zip(ids.map(String::from)).collect()
+    }
+
+    #[test]
+    fn swapping_partners_pairs_north_with_the_former_east() {
+        let seats: Vec<_> = ALL_POSITIONS.map(swapped).into();
+        assert_eq!(seats, [PlayerPosition::North, PlayerPosition::South, PlayerPosition::East, PlayerPosition::West]);
+        assert_eq!(swapped(PlayerPosition::East).team(), PlayerPosition::North.team());
+        assert_ne!(swapped(PlayerPosition::South).team(), PlayerPosition::North.team());
+    }
+
+    #[test]
+    fn series_wins_follow_players_across_swaps() {
+        let mut series = Series::default();
+        series.record(&lineup(["a", "b", "c", "d"]), 1);
+        // После смены партнёров a играет с b
+        let swapped_lineup: HashMap<_, _> = lineup(["a", "b", "c", "d"]).into_iter()
+            .map(|(pos, uid)| (swapped(pos), uid))
+            .collect();
+        series.record(&swapped_lineup, 1);
+
+        assert_eq!(series.games, 2);
+        assert_eq!(series.wins["a"], 2);
+        assert_eq!(series.wins["b"], 1);
+        assert_eq!(series.wins["c"], 1);
+        assert_eq!(series.wins["d"], 0);
+        let WSEvent::SeriesScore { games, wins } = series.event(&swapped_lineup) else { panic!("not a series score") };
+        assert_eq!(games, 2);
+        assert_eq!(wins[&PlayerPosition::South], 1);
+        assert_eq!(wins[&PlayerPosition::East], 1);
+    }
+
+    fn human(id: &str) -> (Seat, UnboundedReceiver<WSEvent>) {
+        let (sender, receiver) = unbounded_channel();
+        (Seat::Human(PlayerSession::new(id.to_string(), id.to_string(), false, sender)), receiver)
+    }
+
+    #[tokio::test]
+    async fn rematch_waits_for_every_human_and_a_refusal_closes_the_room() {
+        let manager = GameManager::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let (north, _) = human("north");
+        let (south, mut south_events) = human("south");
+        let room = Arc::new(GameRoom::new(HashMap::from([
+            (PlayerPosition::North, north),
+            (PlayerPosition::East, Seat::Bot(BotPlayer::new(BotKind::Random))),
+            (PlayerPosition::South, south),
+            (PlayerPosition::West, Seat::Bot(BotPlayer::new(BotKind::Random))),
+        ]), false));
+        manager.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+
+        assert_eq!(manager.rematch("north", true, false).await, Err("Game is not over"));
+        manager.offer_rematch(&room).await;
+        manager.rematch("north", true, true).await.unwrap();
+        // Бот не голосует, но второй человек ещё не ответил
+        assert_eq!(*room.rematch_votes.lock().await, HashMap::from([(PlayerPosition::North, true)]));
+        assert!(manager.active_rooms.lock().await.contains_key(&room.id));
+
+        manager.rematch("south", false, false).await.unwrap();
+        assert!(room.is_closed());
+        assert!(manager.active_rooms.lock().await.is_empty());
+        let mut closed = false;
+        while let Ok(event) = south_events.try_recv() {
+            closed |= matches!(event, WSEvent::GameClose { ref reason } if reason == "Rematch declined");
+        }
+        assert!(closed);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+use crate::core::manager::{GameManager, GameRoom, EYES_TO_WIN};
+use crate::utils::schemas::{PlayerPosition, WSEvent, WSGameOver};
+
+/// An abort vote may only be started while this many cards are out in the
+/// first deal, i.e. during the first two tricks.
+const ABORT_MAX_CARDS_PLAYED: usize = 8;
+/// How long the table has to agree once an abort vote was started.
+pub const ABORT_VOTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Seats that agreed to abort the game and when the vote was started.
+#[derive(Debug)]
+pub struct AbortVote {
+    pub started: Instant,
+    pub votes: HashSet<PlayerPosition>,
+}
+
+impl GameManager {
+    /// Team resignation. The first call proposes it to the partner, the
+    /// partner's call confirms it; the proposer may withdraw it by declining
+    /// their own offer. The resigning team loses as if the
+    /// opponents had reached the winning eye count, which is what ratings see.
+    pub async fn resign(&self, uid: &str, accept: bool) -> Result<(), &'static str> {
+        let (room, pos) = self.find_room_by_uid(uid).await.ok_or("Not in game")?;
+        if room.finished_at.lock().await.is_some() {
+            return Err("Game is over");
+        }
+        let partner = pos.next().next();
+
+        let confirmed = {
+            let mut offer = room.resign_offer.lock().await;
+            match *offer {
+                Some(proposer) if proposer == partner => {
+                    *offer = None;
+                    accept
+                }
+                Some(proposer) if proposer == pos && accept => return Err("Waiting for partner"),
+                // Предложивший передумал: снимаем предложение
+                Some(proposer) if proposer == pos => {
+                    *offer = None;
+                    info!("Room {}: {uid} withdrew the resign offer", room.id);
+                    false
+                }
+                _ if !accept => return Err("Nothing to decline"),
+                // Бот-партнёр всегда соглашается
+                _ if room.bot_for(partner).await.is_some() => {
+                    *offer = None;
+                    true
+                }
+                _ => {
+                    *offer = Some(pos);
+                    room.send_to(partner, WSEvent::ResignProposed { position: pos }).await;
+                    info!("Room {}: {uid} proposed to resign", room.id);
+                    return Ok(());
+                }
+            }
+        };
+
+        if !confirmed {
+            room.send_to(partner, WSEvent::ResignDeclined { position: pos }).await;
+            return Ok(());
+        }
+
+        let team = pos.team();
+        let winner_team = if team == 1 { 2 } else { 1 };
+        {
+            let mut state = room.state.lock().await;
+            let eyes = state.team_eye.entry(winner_team).or_insert(0);
+            *eyes = (*eyes).max(EYES_TO_WIN);
+
+            room.broadcast(WSEvent::TeamResigned { team }).await;
+            room.broadcast(WSEvent::EyeUpdated {
+                team_a: state.team_eye.get(&1).copied().unwrap_or(0),
+                team_b: state.team_eye.get(&2).copied().unwrap_or(0),
+            }).await;
+            room.broadcast(WSEvent::GameOver(WSGameOver {
+                scores: state.team_scores.clone(),
+            })).await;
+        }
+        info!("Room {}: team {team} resigned", room.id);
+
+        self.finish_game(&room, winner_team).await;
+        Ok(())
+    }
+
+    /// Abort vote for the opening of a game. Needs every human player within
+    /// `ABORT_VOTE_TIMEOUT`; any refusal cancels the vote, and so does play
+    /// moving past the opening. An aborted game has no winner, is not rated
+    /// and does not count towards a rematch series.
+    pub async fn vote_abort(&self, uid: &str, accept: bool) -> Result<(), &'static str> {
+        let (room, pos) = self.find_room_by_uid(uid).await.ok_or("Not in game")?;
+        if room.finished_at.lock().await.is_some() {
+            return Err("Game is over");
+        }
+
+        let all_agreed = {
+            let state = room.state.lock().await;
+            let cards_played = 32 - state.hands.values().map(|h| h.len()).sum::<usize>();
+            let too_late = !state.is_first_round || cards_played > ABORT_MAX_CARDS_PLAYED;
+            let mut vote = room.abort_vote.lock().await;
+            if vote.as_ref().is_some_and(|vote| vote.started.elapsed() > ABORT_VOTE_TIMEOUT) {
+                *vote = None;
+                room.broadcast(WSEvent::AbortVoteFailed).await;
+            }
+
+            match vote.as_mut() {
+                None => {
+                    if !accept {
+                        return Err("No abort vote in progress");
+                    }
+                    if too_late {
+                        return Err("Too late to abort");
+                    }
+                    *vote = Some(AbortVote { started: Instant::now(), votes: HashSet::from([pos]) });
+                    room.broadcast(WSEvent::AbortVoteStarted { position: pos }).await;
+                    // За столом с ботами инициатор может оказаться единственным человеком
+                    1 >= room.human_voters().await
+                }
+                Some(_) if !accept => {
+                    *vote = None;
+                    room.broadcast(WSEvent::AbortVote { position: pos, accept }).await;
+                    room.broadcast(WSEvent::AbortVoteFailed).await;
+                    return Ok(());
+                }
+                // Пока голосовали, партия ушла дальше первых взяток
+                Some(_) if too_late => {
+                    *vote = None;
+                    room.broadcast(WSEvent::AbortVoteFailed).await;
+                    return Err("Too late to abort");
+                }
+                Some(vote) => {
+                    vote.votes.insert(pos);
+                    room.broadcast(WSEvent::AbortVote { position: pos, accept }).await;
+                    vote.votes.len() >= room.human_voters().await
+                }
+            }
+        };
+
+        if all_agreed {
+            room.broadcast(WSEvent::GameAborted).await;
+            info!("Room {}: aborted by vote", room.id);
+            self.close_room(&room.id, "Aborted").await;
+        }
+        Ok(())
+    }
+
+    /// Cancels abort votes the table did not finish in time.
+    pub async fn expire_abort_votes(&self) {
+        let rooms: Vec<Arc<GameRoom>> = self.active_rooms.lock().await.values().cloned().collect();
+        for room in rooms {
+            let mut vote = room.abort_vote.lock().await;
+            if vote.as_ref().is_some_and(|vote| vote.started.elapsed() > ABORT_VOTE_TIMEOUT) {
+                *vote = None;
+                room.broadcast(WSEvent::AbortVoteFailed).await;
+                info!("Room {}: abort vote timed out", room.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use sqlx::PgPool;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use crate::core::manager::{PlayerSession, Seat, ALL_POSITIONS};
+
+    /// A table of four humans, with the events South receives.
+    async fn table() -> (GameManager, Arc<GameRoom>, UnboundedReceiver<WSEvent>) {
+        let manager = GameManager::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let mut south_events = None;
+        let mut seats = HashMap::new();
+        for pos in ALL_POSITIONS {
+            let (sender, receiver) = unbounded_channel();
+            let id = format!("{pos:?}").to_lowercase();
+            seats.insert(pos, Seat::Human(PlayerSession::new(id.clone(), id, false, sender)));
+            if pos == PlayerPosition::South {
+                south_events = Some(receiver);
+            }
+        }
+        let room = Arc::new(GameRoom::new(seats, false));
+        manager.active_rooms.lock().await.insert(room.id.clone(), room.clone());
+        (manager, room, south_events.unwrap())
+    }
+
+    fn vote_failed(events: &mut UnboundedReceiver<WSEvent>) -> bool {
+        let mut failed = false;
+        while let Ok(event) = events.try_recv() {
+            failed |= matches!(event, WSEvent::AbortVoteFailed);
+        }
+        failed
+    }
+
+    #[tokio::test]
+    async fn every_accept_checks_the_abort_window() {
+        let (manager, room, mut events) = table().await;
+        manager.vote_abort("north", true).await.unwrap();
+        manager.vote_abort("east", true).await.unwrap();
+        assert!(!vote_failed(&mut events));
+
+        // Первая сдача закончилась, пока шло голосование
+        room.state.lock().await.is_first_round = false;
+        assert_eq!(manager.vote_abort("south", true).await, Err("Too late to abort"));
+        assert!(room.abort_vote.lock().await.is_none());
+        assert!(vote_failed(&mut events));
+        assert_eq!(manager.vote_abort("west", true).await, Err("Too late to abort"));
+        assert!(!room.is_closed());
+    }
+
+    #[tokio::test]
+    async fn unfinished_abort_votes_time_out() {
+        let (manager, room, mut events) = table().await;
+        manager.vote_abort("north", true).await.unwrap();
+        manager.expire_abort_votes().await;
+        assert!(room.abort_vote.lock().await.is_some());
+
+        let earlier = Instant::now().checked_sub(ABORT_VOTE_TIMEOUT + Duration::from_secs(1)).unwrap();
+        room.abort_vote.lock().await.as_mut().unwrap().started = earlier;
+        manager.expire_abort_votes().await;
+        assert!(room.abort_vote.lock().await.is_none());
+        assert!(vote_failed(&mut events));
+        assert_eq!(manager.vote_abort("south", false).await, Err("No abort vote in progress"));
+    }
+
+    #[tokio::test]
+    async fn the_table_aborts_once_every_human_agrees() {
+        let (manager, room, _events) = table().await;
+        for uid in ["north", "east", "south"] {
+            manager.vote_abort(uid, true).await.unwrap();
+        }
+        assert!(!room.is_closed());
+        manager.vote_abort("west", true).await.unwrap();
+        assert!(room.is_closed());
+        assert!(manager.active_rooms.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use crate::core::manager::{GameManager, GameRoom, PlayerSession};
+use crate::core::recovery::save_checkpoint;
+use crate::utils::schemas::WSEvent;
+
+const DRAIN_POLL: Duration = Duration::from_millis(500);
+
+/// Resolves on SIGTERM or Ctrl+C.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+impl GameManager {
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Stops new games and deals, warns everyone, lets the running deals
+    /// finish until `deadline` and checkpoints what is still being played,
+    /// so the rooms come back after the restart.
+    pub async fn shutdown(&self, deadline: Duration) {
+        if self.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        info!("Shutting down: draining rooms for up to {deadline:?}");
+        let notice = WSEvent::Maintenance { seconds: deadline.as_secs() };
+
+        // Очереди и лобби не переживут рестарт: предупреждаем и распускаем
+        let mut waiting: Vec<Arc<Mutex<PlayerSession>>> = vec![];
+        for (_, queue) in self.waiting_queue.lock().await.drain() {
+            waiting.extend(queue.iter().flat_map(|(entry, _)| entry.members().iter().cloned()));
+        }
+        waiting.extend(self.pending_parties.lock().await.drain().map(|(_, party)| party.session));
+        waiting.extend(self.private_lobbies.lock().await.drain().flat_map(|(_, lobby)| lobby.seats.into_values()));
+        for player in waiting {
+            let _ = player.lock().await.sender.send(notice.clone());
+        }
+
+        let rooms: Vec<Arc<GameRoom>> = self.active_rooms.lock().await.values().cloned().collect();
+        let mut deals_done = HashMap::new();
+        for room in &rooms {
+            room.broadcast(notice.clone()).await;
+            let state = room.state.lock().await;
+            // Сдача без сыгранных карт уже «закончена»
+            let done = if state.deal_plays.is_empty() { 0 } else { 1 };
+            deals_done.insert(room.id.clone(), room.history.lock().await.len() + done);
+        }
+
+        let started = Instant::now();
+        loop {
+            let mut busy = 0;
+            for room in &rooms {
+                let finished = room.is_closed() || room.finished_at.lock().await.is_some();
+                if !finished && room.history.lock().await.len() < deals_done[&room.id] {
+                    busy += 1;
+                }
+            }
+            if busy == 0 {
+                info!("All rooms drained in {:?}", started.elapsed());
+                break;
+            }
+            if started.elapsed() >= deadline {
+                warn!("Drain deadline reached with {busy} deals still running");
+                break;
+            }
+            tokio::time::sleep(DRAIN_POLL).await;
+        }
+
+        let mut saved = 0;
+        for room in &rooms {
+            if room.is_closed() || room.finished_at.lock().await.is_some() {
+                continue;
+            }
+            match save_checkpoint(self.db(), room).await {
+                Ok(()) => saved += 1,
+                Err(e) => error!("Room {}: checkpoint failed: {e:?}", room.id),
+            }
+        }
+        info!("Checkpointed {saved} rooms, ready to exit");
+    }
+}
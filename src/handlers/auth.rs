@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Json, State},
+    extract::{Extension, Json, State},
     http::StatusCode,
     response::IntoResponse,
 };
@@ -8,8 +8,10 @@ use sqlx::PgPool;
 use std::sync::Arc;
 use tracing::{error, info};
 
-use crate::utils::telegram::verify_telegram_auth;
-use crate::utils::jwt::{generate_token, validate_token};
+use crate::core::context::AppContext;
+use crate::utils::jwt::{authorize, JwtKeys};
+use crate::utils::refresh_token::{issue_refresh_token, revoke_refresh_family, rotate_refresh_token, RefreshError};
+use crate::utils::telegram::{verify_telegram_auth, verify_telegram_signature, verify_telegram_widget, TelegramAuthError, TelegramUser, TelegramWidgetAuth};
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
@@ -29,10 +31,16 @@ pub struct MeRequest {
 
 #[derive(Serialize)]
 pub struct MeResponse {
+    pub id: i32,
     pub username: String,
     pub rating: u64,
 }
 
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
 #[derive(Serialize)]
 pub struct TokenResponse {
     pub access_token: String,
@@ -41,78 +49,176 @@ pub struct TokenResponse {
 
 pub async fn telegram_login(
     State(pool): State<Arc<PgPool>>,
+    Extension(app_ctx): Extension<Arc<AppContext>>,
     Json(payload): Json<TelegramAuthRequest>,
 ) -> impl IntoResponse {
-    let bot_token = match std::env::var("BOT_TOKEN") {
-        Ok(t) => t,
-        Err(_) => {
-            error!("BOT_TOKEN is not set in environment");
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Server config error").into_response();
+    let settings = app_ctx.settings();
+
+    // Без токена бота проверяем подпись Telegram (Ed25519)
+    let verified = match settings.telegram_bot_id() {
+        Some(bot_id) if settings.bot_token.is_empty() => {
+            verify_telegram_signature(&payload.init_data, bot_id, app_ctx.telegram_keys(), settings.telegram_max_auth_age())
         }
+        _ => verify_telegram_auth(&payload.init_data, &settings.bot_token, settings.telegram_max_auth_age()),
     };
+    match verified {
+        Ok(init_data) => {
+            let Some(tg_user) = init_data.user else {
+                return (StatusCode::BAD_REQUEST, "initData has no user").into_response();
+            };
+            info!("Telegram login {} (start_param: {:?})", tg_user.id, init_data.start_param);
+            login_telegram_user(&pool, app_ctx.jwt(), tg_user).await
+        }
+        Err(err) => rejected(err),
+    }
+}
+
+/// Login from a regular browser through the Telegram Login Widget.
+pub async fn telegram_widget_login(
+    State(pool): State<Arc<PgPool>>,
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<TelegramWidgetAuth>,
+) -> impl IntoResponse {
+    let settings = app_ctx.settings();
+    if settings.bot_token.is_empty() {
+        return (StatusCode::NOT_IMPLEMENTED, "Widget login needs the bot token").into_response();
+    }
 
-    info!("payload: {:?}", &payload.init_data);
+    match verify_telegram_widget(&payload, &settings.bot_token, settings.telegram_max_auth_age()) {
+        Ok(tg_user) => {
+            info!("Telegram widget login {}", tg_user.id);
+            login_telegram_user(&pool, app_ctx.jwt(), tg_user).await
+        }
+        Err(err) => rejected(err),
+    }
+}
 
-    match verify_telegram_auth(&payload.init_data, &bot_token) {
-        Ok(init_data) => {
-            let telegram_id = init_data.user.id.to_string();
-            let username = init_data
-                .user
-                .username
-                .clone()
-                .unwrap_or(init_data.user.first_name.clone());
-
-            let user = sqlx::query!(
-                "INSERT INTO users (telegram_id, username)
-                VALUES ($1, $2)
-                ON CONFLICT (telegram_id) DO UPDATE SET username = EXCLUDED.username
-                RETURNING username",
-                telegram_id,
-                username
-            )
-            .fetch_one(&*pool)
-            .await;
-
-            let final_username = match user {
-                Ok(record) => record.username.unwrap_or_else(|| "anon".to_string()),
-                Err(err) => {
-                    error!("DB error: {:?}", err);
-                    panic!("DB error: {:?}", err);
-                }
+fn rejected(err: TelegramAuthError) -> axum::response::Response {
+    error!("Telegram login rejected: {err}");
+    match err {
+        TelegramAuthError::MissingField(_) | TelegramAuthError::BadField(_) => (StatusCode::BAD_REQUEST, "Malformed Telegram login data"),
+        TelegramAuthError::Expired { .. } => (StatusCode::UNAUTHORIZED, "Telegram login expired, log in again"),
+        TelegramAuthError::FromTheFuture { .. } => (StatusCode::UNAUTHORIZED, "Telegram login is dated in the future"),
+        TelegramAuthError::BadSignature => (StatusCode::UNAUTHORIZED, "Bad Telegram signature"),
+    }.into_response()
+}
+
+/// Creates or updates the account of a verified Telegram user and starts
+/// a new login for it.
+async fn login_telegram_user(pool: &PgPool, jwt: &JwtKeys, tg_user: TelegramUser) -> axum::response::Response {
+    let telegram_id = tg_user.id.to_string();
+    let username = tg_user.username.unwrap_or(tg_user.first_name);
+
+    let result = async {
+        let user_id = sqlx::query_scalar!(
+            "INSERT INTO users (telegram_id, username)
+            VALUES ($1, $2)
+            ON CONFLICT (telegram_id) DO UPDATE SET username = EXCLUDED.username
+            RETURNING id",
+            telegram_id,
+            username
+        )
+        .fetch_one(pool)
+        .await?;
+        let refresh_token = issue_refresh_token(pool, user_id).await?;
+        Ok::<_, sqlx::Error>((user_id, refresh_token))
+    }.await;
+
+    match result {
+        Ok((user_id, refresh_token)) => token_response(jwt, user_id, Some(&telegram_id), refresh_token),
+        Err(err) => {
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
+        }
+    }
+}
+
+/// A fresh one-hour access token next to `refresh_token`.
+fn token_response(jwt: &JwtKeys, user_id: i32, telegram_id: Option<&str>, refresh_token: String) -> axum::response::Response {
+    match jwt.generate_token(user_id, telegram_id, Some(3600)) {
+        Ok(access_token) => {
+            let response = TokenResponse {
+                access_token,
+                refresh_token,
             };
-            match (
-                generate_token(final_username.as_str(), Some(3600)),       // 1 hour
-                generate_token(final_username.as_str(), Some(7 * 24 * 3600)) // 7 days
-            ) {
-                (Ok(access_token), Ok(refresh_token)) => {
-                    let response = TokenResponse {
-                        access_token,
-                        refresh_token,
-                    };
-                    (StatusCode::OK, Json(response)).into_response()
-                }
-                _ => (StatusCode::INTERNAL_SERVER_ERROR, "JWT generation failed").into_response(),
-            }
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "JWT generation failed").into_response(),
+    }
+}
+
+/// Rotates the refresh token: the one sent is spent and a new pair comes
+/// back. Sending a spent token again logs that login out everywhere.
+pub async fn refresh(
+    State(pool): State<Arc<PgPool>>,
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<RefreshRequest>,
+) -> impl IntoResponse {
+    let result = async {
+        let (user_id, refresh_token) = match rotate_refresh_token(&pool, &payload.refresh_token).await? {
+            Ok(rotated) => rotated,
+            Err(err) => return Ok(Err(err)),
+        };
+        let telegram_id = sqlx::query_scalar!("SELECT telegram_id FROM users WHERE id = $1", user_id)
+            .fetch_one(&*pool)
+            .await?;
+        Ok(Ok((user_id, telegram_id, refresh_token)))
+    }.await;
+
+    match result {
+        Ok(Ok((user_id, telegram_id, refresh_token))) => token_response(app_ctx.jwt(), user_id, telegram_id.as_deref(), refresh_token),
+        Ok(Err(RefreshError::Reused)) => (StatusCode::UNAUTHORIZED, "Refresh token reused, log in again").into_response(),
+        Ok(Err(RefreshError::Invalid)) => (StatusCode::UNAUTHORIZED, "Incorrect refresh token").into_response(),
+        Err::<_, sqlx::Error>(err) => {
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
+        }
+    }
+}
+
+/// Revokes the login the refresh token belongs to. Access tokens already
+/// issued stay valid until they expire.
+pub async fn logout(
+    State(pool): State<Arc<PgPool>>,
+    Json(payload): Json<RefreshRequest>,
+) -> impl IntoResponse {
+    match revoke_refresh_family(&pool, &payload.refresh_token).await {
+        Ok(revoked) => {
+            info!("Logout: {revoked} refresh tokens revoked");
+            StatusCode::NO_CONTENT.into_response()
         }
         Err(err) => {
-            error!("Telegram signature verification failed: {:?}", err);
-            (StatusCode::UNAUTHORIZED, "Bad Telegram signature").into_response()
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
         }
     }
 }
 
-pub async fn me(Json(payload): Json<MeRequest>) -> impl IntoResponse {
-    match validate_token(&payload.token) {
-        Ok(claims) => {
+pub async fn me(
+    State(pool): State<Arc<PgPool>>,
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<MeRequest>,
+) -> impl IntoResponse {
+    let user = match authorize(&pool, app_ctx.jwt(), &payload.token).await {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    let rating = sqlx::query_scalar!("SELECT rating FROM users WHERE id = $1", user.id)
+        .fetch_one(&*pool)
+        .await;
+    match rating {
+        Ok(rating) => {
             let response = MeResponse {
-                username: claims.sub,
-                rating: 99,
+                id: user.id,
+                username: user.name,
+                rating: rating.round().max(0.0) as u64,
             };
             (StatusCode::OK, Json(response)).into_response()
         }
         Err(err) => {
-            error!("Invalid token: {:?}", err);
-            (StatusCode::UNAUTHORIZED, "Incorrect token").into_response()
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
         }
     }
 }
@@ -0,0 +1,163 @@
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::{error, info};
+
+use crate::utils::api_token::generate_api_token;
+use crate::core::context::AppContext;
+use crate::utils::jwt::{authorize, JwtKeys};
+
+/// Bot accounts one person may own.
+const MAX_BOTS_PER_OWNER: i64 = 5;
+
+#[derive(Deserialize)]
+pub struct BotRequest {
+    pub token: String,
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct BotTokenResponse {
+    pub username: String,
+    /// Shown only once; the server keeps just its hash.
+    pub api_token: String,
+}
+
+fn valid_bot_name(name: &str) -> bool {
+    (3..=32).contains(&name.len()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Id of the person behind a user JWT.
+async fn owner_id(pool: &PgPool, token: &str, jwt: &JwtKeys) -> Result<i32, (StatusCode, &'static str)> {
+    authorize(pool, jwt, token).await.map(|user| user.id)
+}
+
+/// Creates a bot account owned by the caller and returns its API token.
+pub async fn create_bot(
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<BotRequest>,
+) -> impl IntoResponse {
+    let pool = app_ctx.db();
+    let owner = match owner_id(pool, &payload.token, app_ctx.jwt()).await {
+        Ok(owner) => owner,
+        Err(err) => return err.into_response(),
+    };
+    if !valid_bot_name(&payload.name) {
+        return (StatusCode::BAD_REQUEST, "Bot name must be 3-32 letters, digits or _").into_response();
+    }
+
+    let result: Result<Result<BotTokenResponse, (StatusCode, &str)>, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        // Блокировка владельца: параллельные создания ждут друг друга, лимит не обойти
+        sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", owner)
+            .fetch_one(&mut *tx)
+            .await?;
+        let owned = sqlx::query_scalar!(
+            r#"SELECT count(*) AS "count!" FROM users WHERE owner_id = $1"#,
+            owner
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if owned >= MAX_BOTS_PER_OWNER {
+            return Ok(Err((StatusCode::FORBIDDEN, "Too many bot accounts")));
+        }
+
+        let username = format!("bot:{}", payload.name);
+        let bot_id = sqlx::query_scalar!(
+            "INSERT INTO users (bot_name, username, is_bot, owner_id)
+            VALUES ($1, $2, true, $3)
+            ON CONFLICT (bot_name) DO NOTHING
+            RETURNING id",
+            payload.name,
+            username,
+            owner
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(bot_id) = bot_id else {
+            return Ok(Err((StatusCode::CONFLICT, "Bot name is taken")));
+        };
+
+        let (api_token, hash) = generate_api_token();
+        sqlx::query!(
+            "INSERT INTO api_tokens (user_id, token_hash) VALUES ($1, $2)",
+            bot_id,
+            hash
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        info!("Bot account {username} created by user {owner}");
+        Ok(Ok(BotTokenResponse { username, api_token }))
+    }.await;
+
+    match result {
+        Ok(Ok(response)) => (StatusCode::OK, Json(response)).into_response(),
+        Ok(Err(err)) => err.into_response(),
+        Err(err) => {
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
+        }
+    }
+}
+
+/// Revokes every token of one of the caller's bots and issues a new one.
+pub async fn rotate_bot_token(
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<BotRequest>,
+) -> impl IntoResponse {
+    let pool = app_ctx.db();
+    let owner = match owner_id(pool, &payload.token, app_ctx.jwt()).await {
+        Ok(owner) => owner,
+        Err(err) => return err.into_response(),
+    };
+
+    let username = format!("bot:{}", payload.name);
+    let (api_token, hash) = generate_api_token();
+    let result: Result<Option<i32>, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        let bot_id = sqlx::query_scalar!(
+            "SELECT id FROM users WHERE bot_name = $1 AND is_bot AND owner_id = $2",
+            payload.name,
+            owner
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(bot_id) = bot_id else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            "UPDATE api_tokens SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+            bot_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "INSERT INTO api_tokens (user_id, token_hash) VALUES ($1, $2)",
+            bot_id,
+            hash
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some(bot_id))
+    }.await;
+
+    match result {
+        Ok(Some(_)) => {
+            info!("Bot account {username}: API token rotated");
+            (StatusCode::OK, Json(BotTokenResponse { username, api_token })).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Bot not found").into_response(),
+        Err(err) => {
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use axum::{
+    extract::{Extension, Json, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::error;
+
+use crate::ai::analysis::{analyze_game, GameAnalysis};
+use crate::core::context::AppContext;
+use crate::core::history::{list_games, load_game_analysis, load_game_history, save_game_analysis, GameSummary};
+use crate::utils::jwt::authorize;
+use crate::utils::schemas::PlayerPosition;
+
+/// How many recent games the history list returns.
+const GAMES_LIST_LIMIT: i64 = 20;
+/// Seconds a client is asked to wait when every analysis slot is busy.
+const ANALYSIS_RETRY_AFTER_SECS: u64 = 30;
+
+#[derive(Deserialize)]
+pub struct GamesRequest {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+pub struct GamesResponse {
+    pub games: Vec<GameSummary>,
+}
+
+#[derive(Serialize)]
+pub struct AnalysisResponse {
+    pub game_id: String,
+    pub position: PlayerPosition,
+    #[serde(flatten)]
+    pub analysis: GameAnalysis,
+}
+
+pub async fn my_games(
+    State(pool): State<Arc<PgPool>>,
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<GamesRequest>,
+) -> impl IntoResponse {
+    let user = match authorize(&pool, app_ctx.jwt(), &payload.token).await {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    match list_games(&pool, &user.id.to_string(), GAMES_LIST_LIMIT).await {
+        Ok(games) => (StatusCode::OK, Json(GamesResponse { games })).into_response(),
+        Err(err) => {
+            error!("DB error: {:?}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response()
+        }
+    }
+}
+
+/// Post-game review of every play, computed on first request and kept
+/// with the game. Only players of the game may see it.
+pub async fn game_analysis(
+    State(pool): State<Arc<PgPool>>,
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Path(game_id): Path<String>,
+    Json(payload): Json<GamesRequest>,
+) -> impl IntoResponse {
+    let user = match authorize(&pool, app_ctx.jwt(), &payload.token).await {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    let history = match load_game_history(&pool, &game_id).await {
+        Ok(Some(history)) => history,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Game not found").into_response(),
+        Err(err) => {
+            error!("DB error: {:?}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response();
+        }
+    };
+    // Чужие партии не показываем, даже не подтверждаем их существование
+    let Some(position) = history.position_of(&user.id.to_string()) else {
+        return (StatusCode::NOT_FOUND, "Game not found").into_response();
+    };
+
+    match load_game_analysis(&pool, &game_id).await {
+        Ok(Some(analysis)) => return (StatusCode::OK, Json(AnalysisResponse { game_id, position, analysis })).into_response(),
+        Ok(None) => {}
+        Err(err) => {
+            error!("DB error: {:?}", err);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response();
+        }
+    }
+
+    // Разбор партии занимает ядро на десятки секунд, поэтому их число ограничено
+    let Ok(permit) = app_ctx.analysis_slots().try_acquire_owned() else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, ANALYSIS_RETRY_AFTER_SECS.to_string())],
+            "Analysis is busy, try again later",
+        )
+            .into_response();
+    };
+    let analysis = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        analyze_game(&history.deals)
+    })
+    .await;
+    match analysis {
+        Ok(Ok(analysis)) => {
+            if let Err(err) = save_game_analysis(&pool, &game_id, &analysis).await {
+                error!("Game {game_id}: failed to save analysis: {err:?}");
+            }
+            (StatusCode::OK, Json(AnalysisResponse { game_id, position, analysis })).into_response()
+        }
+        Ok(Err(err)) => {
+            error!("Game {game_id}: broken history: {err}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Broken game history").into_response()
+        }
+        Err(err) => {
+            error!("Game {game_id}: analysis failed: {err:?}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Analysis failed").into_response()
+        }
+    }
+}
@@ -1,2 +1,5 @@
 pub mod auth;
+pub mod bots;
+pub mod games;
+pub mod rooms;
 pub mod ws;
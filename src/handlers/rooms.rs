@@ -0,0 +1,46 @@
+use axum::{
+    extract::{Extension, Json},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::core::context::AppContext;
+use crate::utils::jwt::authorize;
+
+#[derive(Deserialize)]
+pub struct CreateRoomRequest {
+    pub token: String,
+}
+
+#[derive(Serialize)]
+pub struct CreateRoomResponse {
+    pub code: String,
+    /// Mini App link; only when `BOT_USERNAME` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invite_link: Option<String>,
+}
+
+pub async fn create_room(
+    Extension(app_ctx): Extension<Arc<AppContext>>,
+    Json(payload): Json<CreateRoomRequest>,
+) -> impl IntoResponse {
+    let settings = app_ctx.settings();
+    let user = match authorize(app_ctx.db(), app_ctx.jwt(), &payload.token).await {
+        Ok(user) => user,
+        Err(err) => return err.into_response(),
+    };
+
+    if app_ctx.game_manager().is_shutting_down() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is restarting").into_response();
+    }
+
+    let code = app_ctx.game_manager().create_private_room(&user.id.to_string()).await;
+    let response = CreateRoomResponse {
+        invite_link: (!settings.bot_username.is_empty())
+            .then(|| format!("https://t.me/{}?startapp={code}", settings.bot_username)),
+        code,
+    };
+    (StatusCode::OK, Json(response)).into_response()
+}
@@ -3,15 +3,15 @@
 use axum::{extract::ws::{Message, WebSocket, WebSocketUpgrade}, response::IntoResponse, extract::Extension};
 use futures_util::StreamExt;
 use futures_util::SinkExt;
-use uuid::Bytes;
-use std::{sync::{Arc, atomic::AtomicBool}};
+use std::sync::Arc;
 use tokio::sync::{Mutex, mpsc};
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 
 use crate::{
+    ai::BotKind,
     core::context::AppContext,
     core::manager::{PlayerSession},
-    utils::schemas::{Card, Rank, Suit, PlayerPosition, WSIncomingMessage, SubOrUnsub, WSEvent, WSCardPlayed, WSGameOver, WSTrickWon, WSYourHand, WSYourTurn},
+    utils::schemas::{WSIncomingMessage, SubOrUnsub, WSEvent},
     utils::jwt::handle_auth,
 };
 
@@ -24,9 +24,11 @@ pub async fn ws_handler(
 }
 
 async fn handle_socket(socket: WebSocket, app_ctx: Arc<AppContext>) {
-    let (mut write, mut read) = socket.split();
+    let (write, mut read) = socket.split();
     let write_arc = Arc::new(Mutex::new(write));
     let mut client_uid: Option<String> = None;
+    let mut client_is_bot = false;
+    let mut client_name = String::new();
     let (tx, mut rx) = mpsc::unbounded_channel();
     let gm = app_ctx.game_manager();
 
@@ -64,141 +66,110 @@ async fn handle_socket(socket: WebSocket, app_ctx: Arc<AppContext>) {
 
                 match incoming {
                     WSIncomingMessage::Auth(auth_msg) => {
-                        if let Some(uid) = handle_auth(auth_msg, &write_arc).await {
+                        if let Some(identity) = handle_auth(auth_msg, app_ctx.db(), app_ctx.jwt(), &write_arc).await {
+                            let uid = identity.uid;
                             client_uid = Some(uid.clone());
-                            info!("User {uid} authenticated");
+                            client_is_bot = identity.is_bot;
+                            client_name = identity.name;
+                            info!("User {uid} ({client_name}) authenticated (bot: {client_is_bot})");
 
                             if let Some(existing_player) = gm.find_player_by_uid(&uid).await {
                                 let mut player_guard = existing_player.lock().await;
                                 player_guard.sender = tx.clone();
                                 player_guard.mark_as_connected();
+                                *player_guard.last_ping.lock().await = std::time::Instant::now();
                             }
+                            gm.reconnect(&uid).await;
                         } else {
                             let _ = write_arc.lock().await.send(Message::Text("AuthFailed".into())).await;
                             break;
                         }
                     }
 
-                    WSIncomingMessage::Manage(SubOrUnsub::FindGame(_)) => {
+                    WSIncomingMessage::Manage(SubOrUnsub::FindGame(msg)) => {
                         if let Some(uid) = &client_uid {
-                            let player = PlayerSession::new(uid.clone(), tx.clone());
-                            gm.join(player.clone()).await;
-
-                            let rooms = gm.active_rooms.lock().await;
-                            for room in rooms.values() {
-                                let state = room.state.lock().await;
-                                for (pos, session) in &room.players {
-                                    let session = session.lock().await;
-                                    if let Some(hand) = state.hands.get(pos) {
-                                        let _ = session.sender.send(WSEvent::YourHand(WSYourHand {
-                                            cards: hand.clone(),
-                                        }));
-                                        if state.current_turn == *pos {
-                                            let _ = session.sender.send(WSEvent::YourTurn(WSYourTurn));
-                                        }
-                                    }
-                                }
-                            }
+                            let player = PlayerSession::new(uid.clone(), client_name.clone(), client_is_bot, tx.clone());
+                            gm.join(player.clone(), msg.partner, msg.queue).await;
                         }
                     }
 
                     WSIncomingMessage::Manage(SubOrUnsub::PlayCard(card)) => {
                         if let Some(uid) = &client_uid {
-                            let card = Card {
-                                rank: match card.rank.unwrap().to_lowercase().as_str() {
-                                    "7" => Rank::Seven,
-                                    "8" => Rank::Eight,
-                                    "9" => Rank::Nine,
-                                    "10" => Rank::Ten,
-                                    "j" => Rank::Jack,
-                                    "q" => Rank::Queen,
-                                    "k" => Rank::King,
-                                    "a" => Rank::Ace,
-                                    _ => { let _ = tx.send(WSEvent::Error { detail: "Invalid rank".to_string() }); continue; }
-                                },
-                                suit: match card.suit.unwrap().to_lowercase().as_str() {
-                                    "c" => Suit::Clubs,
-                                    "d" => Suit::Diamonds,
-                                    "h" => Suit::Hearts,
-                                    "s" => Suit::Spades,
-                                    _ => { let _ = tx.send(WSEvent::Error { detail: "Invalid suit".to_string() }); continue; }
-                                },
+                            let card = match card.card() {
+                                Ok(card) => card,
+                                Err(e) => { let _ = tx.send(WSEvent::Error { detail: e.to_string() }); continue; }
                             };
 
-                            let rooms = gm.active_rooms.lock().await;
-                            for room in rooms.values() {
-                                let mut position: Option<PlayerPosition> = None;
-                                for (p, player) in &room.players {
-                                    if player.lock().await.id == *uid {
-                                        position = Some(*p);
-                                        break;
-                                    }
-                                }
-
-                                if let Some(pos) = position {
-                                    let mut state = room.state.lock().await;
-                                    match state.play_card(pos, card) {
-                                        Ok(_) => {
-                                            for (_, s) in &room.players {
-                                                let session = s.lock().await;
-                                                let _ = session.sender.send(WSEvent::CardPlayed(WSCardPlayed { position: pos, card }));
-                                            }
-
-                                            if let Some(winner) = state.resolve_trick() {
-                                                for (_, s) in &room.players {
-                                                    let session = s.lock().await;
-                                                    let _ = session.sender.send(WSEvent::TrickWon(WSTrickWon { position: winner }));
-                                                }
-
-                                                if state.hands.values().all(|h| h.is_empty()) {
-                                                    let _ = state.update_eye_after_round();
-                                                    let eye = state.team_eye.clone();
-                                                    state.trump = Suit::random_suit(); // TODO dont know!!!
-                                                    let _ = state.update_hands(); //
-
-                                                    for (pos, s) in &room.players {
-                                                        let session = s.lock().await;
-
-                                                        let _ = session.sender.send(WSEvent::EyeUpdated {
-                                                            team_a: eye.get(&1).copied().unwrap_or(0),
-                                                            team_b: eye.get(&2).copied().unwrap_or(0),
-                                                        });
-                                                        let _ = session.sender.send(WSEvent::TrumpUpdated { trump: state.trump.clone() });
-
-                                                        if let Some(hand) = state.hands.get(pos) {
-                                                            let _ = session.sender.send(WSEvent::YourHand(WSYourHand {
-                                                                cards: hand.clone(),
-                                                            }));
-                                                            if state.current_turn == *pos {
-                                                                let _ = session.sender.send(WSEvent::YourTurn(WSYourTurn));
-                                                            }
-                                                        }
-                                                    }
-
-                                                    if eye.get(&1).copied().unwrap_or(0) >= 12 || eye.get(&2).copied().unwrap_or(0) >= 12 {
-                                                        for (_, s) in &room.players {
-                                                            let session = s.lock().await;
-                                                            let _ = session.sender.send(WSEvent::GameOver(WSGameOver {
-                                                                scores: state.team_scores.clone(),
-                                                            }));
-                                                        }
-                                                    }
-                                                } else {
-                                                    let session = room.players.get(&winner).unwrap().lock().await;
-                                                    let _ = session.sender.send(WSEvent::YourTurn(WSYourTurn));
-                                                }
-                                            } else {
-                                                if let Some(s) = room.players.get(&state.current_turn) {
-                                                    let session = s.lock().await;
-                                                    let _ = session.sender.send(WSEvent::YourTurn(WSYourTurn));
-                                                }
-                                            }
-                                        }
-                                        Err(e) => {
-                                            let _ = tx.send(WSEvent::Error { detail: e.to_string() });
-                                        }
-                                    }
-                                }
+                            if let Err(e) = gm.play_card(uid, card).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
+                            }
+                        }
+                    }
+                    WSIncomingMessage::Manage(SubOrUnsub::JoinRoom(msg)) => {
+                        if let Some(uid) = &client_uid {
+                            let (Some(code), Some(position)) = (msg.room_id, msg.position) else {
+                                let _ = tx.send(WSEvent::Error { detail: "room_id and position are required".to_string() });
+                                continue;
+                            };
+                            let player = PlayerSession::new(uid.clone(), client_name.clone(), client_is_bot, tx.clone());
+                            if let Err(e) = gm.join_private_room(&code.to_uppercase(), position, player).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
+                            }
+                        }
+                    }
+
+                    WSIncomingMessage::Manage(SubOrUnsub::LeaveRoom(_)) => {
+                        if let Some(uid) = &client_uid {
+                            gm.leave_private_room(uid).await;
+                        }
+                    }
+
+                    WSIncomingMessage::Manage(SubOrUnsub::StartRoom(msg)) => {
+                        if let Some(uid) = &client_uid {
+                            let Some(code) = msg.room_id else {
+                                let _ = tx.send(WSEvent::Error { detail: "room_id is required".to_string() });
+                                continue;
+                            };
+                            let kind = match msg.bot.as_deref().map(str::parse::<BotKind>) {
+                                Some(Ok(kind)) => kind,
+                                Some(Err(e)) => { let _ = tx.send(WSEvent::Error { detail: e }); continue; }
+                                None => msg.difficulty.map_or(BotKind::Heuristic, BotKind::MonteCarlo),
+                            };
+                            let bots = msg.fill_with_bots.unwrap_or(false).then_some(kind);
+                            if let Err(e) = gm.start_private_room(&code.to_uppercase(), uid, bots).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
+                            }
+                        }
+                    }
+                    WSIncomingMessage::Manage(SubOrUnsub::Rematch(msg)) => {
+                        if let Some(uid) = &client_uid {
+                            let accept = msg.accept.unwrap_or(true);
+                            let swap = msg.swap_partners.unwrap_or(false);
+                            if let Err(e) = gm.rematch(uid, accept, swap).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
+                            }
+                        }
+                    }
+                    WSIncomingMessage::Manage(SubOrUnsub::Resign(msg)) => {
+                        if let Some(uid) = &client_uid {
+                            if let Err(e) = gm.resign(uid, msg.accept.unwrap_or(true)).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
+                            }
+                        }
+                    }
+
+                    WSIncomingMessage::Manage(SubOrUnsub::Abort(msg)) => {
+                        if let Some(uid) = &client_uid {
+                            if let Err(e) = gm.vote_abort(uid, msg.accept.unwrap_or(true)).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
+                            }
+                        }
+                    }
+
+                    WSIncomingMessage::Manage(SubOrUnsub::Hint(_)) => {
+                        if let Some(uid) = &client_uid {
+                            if let Err(e) = gm.hint(uid).await {
+                                let _ = tx.send(WSEvent::Error { detail: e.to_string() });
                             }
                         }
                     }
@@ -206,14 +177,12 @@ async fn handle_socket(socket: WebSocket, app_ctx: Arc<AppContext>) {
                 }
             }
 
-            Message::Binary(data) => {
-                if data.as_ref() == vec![9] {
-                    if let Some(uid) = &client_uid {
-                        if let Some(player) = gm.find_player_by_uid(uid).await {
-                            let player_guard = player.lock().await;
-                            let mut ping_guard = player_guard.last_ping.lock().await;
-                            *ping_guard = std::time::Instant::now();
-                        }
+            Message::Binary(data) if data.as_slice() == [9] => {
+                if let Some(uid) = &client_uid {
+                    if let Some(player) = gm.find_player_by_uid(uid).await {
+                        let player_guard = player.lock().await;
+                        let mut ping_guard = player_guard.last_ping.lock().await;
+                        *ping_guard = std::time::Instant::now();
                     }
                 }
             }
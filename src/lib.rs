@@ -0,0 +1,5 @@
+pub mod handlers;
+pub mod utils;
+pub mod core;
+pub mod ai;
+pub mod config;
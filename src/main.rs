@@ -1,62 +1,143 @@
 use tokio::net::TcpListener;
 use axum::{
-    extract::ws::{WebSocketUpgrade, WebSocket, Message},
     extract::Extension,
-    response::IntoResponse,
     routing::{get, post},
     Router,
 };
-use tracing::{info, warn, error};
-use tracing_subscriber;
-use crate::utils::schemas::{WSIncomingMessage, SubOrUnsub};
-use crate::core::context::{AppContext, set_global_context};
-// use crate::utils::jwt::handle_auth;
-use crate::handlers::auth::{telegram_login, me};
-use crate::handlers::ws::ws_handler;
-use crate::utils::db::pg_pool;
-pub mod handlers;
-pub mod utils;
-pub mod core;
+use tracing::info;
+use ed25519_dalek::VerifyingKey;
+use squirrel_core::ai::external::{parse_external_bots, register_external_bots, ExternalBotSpec};
+use squirrel_core::config::settings::{AppSettings, SettingsError};
+use squirrel_core::core::context::{AppContext, set_global_context};
+use squirrel_core::core::shutdown::shutdown_signal;
+// use squirrel_core::utils::jwt::handle_auth;
+use squirrel_core::handlers::auth::{telegram_login, telegram_widget_login, refresh, logout, me};
+use squirrel_core::handlers::bots::{create_bot, rotate_bot_token};
+use squirrel_core::handlers::games::{game_analysis, my_games};
+use squirrel_core::handlers::rooms::create_room;
+use squirrel_core::handlers::ws::ws_handler;
+use squirrel_core::utils::db::pg_pool;
+use squirrel_core::utils::jwt::JwtKeys;
+use squirrel_core::utils::telegram::parse_public_key;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use futures_util::StreamExt;
-use futures_util::SinkExt;
-use tower_http::cors::{CorsLayer, Any};
-use axum::http::{Method, HeaderName};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use axum::http::{HeaderName, HeaderValue, Method};
 use tower_http::trace::TraceLayer;
 
+/// What the settings describe but can not check by themselves: the JWT
+/// keys, the Telegram public keys and the external bots.
+fn build_from_settings(settings: &AppSettings) -> Result<(JwtKeys, Vec<VerifyingKey>, Vec<ExternalBotSpec>), SettingsError> {
+    let mut problems = vec![];
+    let jwt = JwtKeys::from_settings(settings).map_err(|e| problems.push(e)).ok();
+    let mut telegram_keys = vec![];
+    for key in &settings.telegram_public_keys {
+        match parse_public_key(key) {
+            Ok(key) => telegram_keys.push(key),
+            Err(e) => problems.push(format!("Telegram public key {e}")),
+        }
+    }
+    for entry in settings.external_bots.split(';').filter(|e| !e.trim().is_empty()) {
+        if parse_external_bots(entry, settings.external_bot_timeout()).is_empty() {
+            problems.push(format!("EXTERNAL_BOTS entry `{entry}` is not name=program"));
+        }
+    }
+    match jwt {
+        Some(jwt) if problems.is_empty() => {
+            Ok((jwt, telegram_keys, parse_external_bots(&settings.external_bots, settings.external_bot_timeout())))
+        }
+        _ => Err(SettingsError(problems)),
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    let settings = match AppSettings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let (jwt, telegram_keys, external_bots) = match build_from_settings(&settings) {
+        Ok(built) => built,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
     tracing_subscriber::fmt()
-        .with_env_filter("debug")
+        .with_env_filter(settings.log_filter.as_str())
         .init();
 
     info!("Logical cores: {}", num_cpus::get());
     info!("Physical cores: {}", num_cpus::get_physical());
 
-    let app_ctx = Arc::new(AppContext::new());
+    register_external_bots(external_bots);
+    let pg_pool = match pg_pool(&settings.postgres).await {
+        Ok(pool) => Arc::new(pool),
+        Err(e) => {
+            tracing::error!("Can not connect to Postgres at {}:{}: {e}", settings.postgres.host, settings.postgres.port);
+            std::process::exit(1);
+        }
+    };
+    let bind_addr = settings.bind_addr;
+    let drain = settings.shutdown_drain();
+    let allow_origin = if settings.allows_any_origin() {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(settings.cors_origins.iter().map(|o| o.parse::<HeaderValue>().expect("origins are validated")))
+    };
+    let app_ctx = Arc::new(AppContext::new((*pg_pool).clone(), settings, jwt, telegram_keys));
     let gm = app_ctx.game_manager();
-    let pg_pool = Arc::new(pg_pool().await.expect(""));
     set_global_context(app_ctx.clone());
 
-    gm.start_monitoring();
+    match gm.restore_rooms().await {
+        Ok(restored) => info!("Restored {restored} rooms"),
+        Err(e) => tracing::error!("Failed to restore rooms: {e:?}"),
+    }
+    gm.clone().start_monitoring();
 
 
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(allow_origin)
         .allow_methods([Method::POST, Method::GET, Method::OPTIONS])
         .allow_headers([HeaderName::from_static("content-type"), HeaderName::from_static("authorization")]);
 
     let router = Router::new()
         .route("/v1/ws", get(ws_handler))
         .route("/auth/login", post(telegram_login))
-        .with_state(pg_pool)
+        .route("/auth/widget", post(telegram_widget_login))
+        .route("/auth/refresh", post(refresh))
+        .route("/auth/logout", post(logout))
         .route("/me", post(me))
+        .route("/rooms", post(create_room))
+        .route("/games", post(my_games))
+        .route("/bots", post(create_bot))
+        .route("/bots/token", post(rotate_bot_token))
+        .route("/games/:id/analysis", post(game_analysis))
+        .with_state(pg_pool)
         .layer(cors)
         .layer(Extension(app_ctx))
         .layer(TraceLayer::new_for_http());
 
-    info!("Start server!");
-    let server = TcpListener::bind("0.0.0.0:9221").await.unwrap();
-    axum::serve(server, router.into_make_service()).await.unwrap();
+    info!("Start server on {bind_addr}");
+    let server = match TcpListener::bind(bind_addr).await {
+        Ok(server) => server,
+        Err(e) => {
+            tracing::error!("Can not bind {bind_addr}: {e}");
+            std::process::exit(1);
+        }
+    };
+    let manager = gm.clone();
+    axum::serve(server, router.into_make_service())
+        .with_graceful_shutdown(async move {
+            shutdown_signal().await;
+            manager.shutdown(drain).await;
+        })
+        .await
+        .unwrap();
+    // Итоги, рейтинги и записи партий должны дойти до базы до выхода
+    gm.flush_writes().await;
+    info!("Server stopped");
 }
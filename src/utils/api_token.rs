@@ -0,0 +1,42 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+/// Bot API tokens start with this, which tells them apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "sqb_";
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// A fresh token and the hash to store for it.
+pub fn generate_api_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = format!("{API_TOKEN_PREFIX}{}", hex::encode(bytes));
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// How random tokens (bot API and refresh tokens) are stored: they carry
+/// 256 bits of entropy, so a plain SHA-256 is enough.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Id and username of the bot account owning a valid, not revoked token.
+pub async fn validate_api_token(pool: &PgPool, token: &str) -> Result<Option<(i32, String)>, sqlx::Error> {
+    let user = sqlx::query!(
+        "UPDATE api_tokens SET last_used_at = now()
+        FROM users
+        WHERE api_tokens.token_hash = $1
+            AND api_tokens.revoked_at IS NULL
+            AND users.id = api_tokens.user_id
+            AND users.is_bot
+        RETURNING users.id, users.username",
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user.map(|u| (u.id, u.username.unwrap_or_default())))
+}
@@ -1,26 +1,37 @@
 use sqlx::postgres::PgPool;
 use sqlx::postgres::PgPoolOptions;
 use std::time::Duration;
-use dotenvy::dotenv;
-
-pub async fn pg_pool() -> Result<PgPool, sqlx::Error> {
-    dotenv().ok();
-    let database_url = format!(
-        "postgresql://{}:{}@{}:{}/{}",
-        std::env::var("POSTGRES_USER").unwrap(),
-        std::env::var("POSTGRES_PASSWORD").unwrap(),
-        std::env::var("POSTGRES_HOST").unwrap(),
-        std::env::var("POSTGRES_PORT").unwrap(),
-        std::env::var("POSTGRES_DB").unwrap()
-    );
-
+use crate::config::settings::PostgresSettings;
 
+pub async fn pg_pool(settings: &PostgresSettings) -> Result<PgPool, sqlx::Error> {
     let pool = PgPoolOptions::new()
         .min_connections(0)
-        .max_connections(256)
+        .max_connections(settings.max_connections)
         .acquire_timeout(Duration::from_secs(30))
-        .connect(&database_url)
+        .connect(&settings.url())
         .await?;
     Ok(pool)
 }
 
+/// Helpers for the `#[sqlx::test]` tests. Those get a fresh database
+/// next to `DATABASE_URL` and are ignored by default; run them with
+/// `DATABASE_URL=postgres://... cargo test -- --ignored`.
+#[cfg(test)]
+pub(crate) mod testing {
+    use sqlx::migrate::Migrate;
+    use sqlx::postgres::PgPool;
+
+    /// Applies the migrations up to `until` inclusive to a database
+    /// created with `migrations = false`, so data can be seeded in the old
+    /// schema before `sqlx::migrate!().run` brings it up to date.
+    pub async fn migrate_until(pool: &PgPool, until: i64) {
+        let mut conn = pool.acquire().await.unwrap();
+        conn.ensure_migrations_table().await.unwrap();
+        for migration in sqlx::migrate!().iter() {
+            if migration.migration_type.is_down_migration() || migration.version > until {
+                continue;
+            }
+            conn.apply(migration).await.unwrap_or_else(|e| panic!("migration {}: {e}", migration.version));
+        }
+    }
+}
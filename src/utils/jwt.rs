@@ -1,61 +1,392 @@
+use std::collections::HashMap;
 use jsonwebtoken::EncodingKey;
-use jsonwebtoken::{decode, encode, Header, DecodingKey, Validation, Algorithm, errors::Error};
+use jsonwebtoken::{decode, decode_header, encode, Header, DecodingKey, Validation, Algorithm, errors::Error, errors::ErrorKind};
 use serde::{Serialize, Deserialize};
+use crate::config::settings::{AppSettings, JwtKeySettings, DEFAULT_KID, MIN_SECRET_LEN};
+use crate::utils::api_token::{is_api_token, validate_api_token};
 use crate::utils::schemas::{Auth, WSEvent};
+use sqlx::PgPool;
 use axum::extract::ws::Message;
+use axum::http::StatusCode;
 use std::sync::Arc;
 use std::time::{UNIX_EPOCH, SystemTime};
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{error, warn};
 use futures_util::SinkExt;
-use futures_util::Sink;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    /// `users.id`; the username in tokens of version 0.
     pub sub: String,
+    /// `CLAIMS_VERSION` of the issuer; absent, so 0, in tokens issued
+    /// before `sub` held the user id.
+    #[serde(default)]
+    pub ver: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub telegram_id: Option<String>,
     pub exp: usize,
+    /// The three below are missing from tokens without a `kid`.
+    #[serde(default)]
+    pub iat: usize,
+    #[serde(default)]
+    pub iss: String,
+    #[serde(default)]
+    pub aud: String,
 }
 
-const SECRET: &[u8] = b"fuckyou";
+/// Version of the claims `generate_token` issues.
+pub const CLAIMS_VERSION: u32 = 1;
 
-pub fn validate_token(token: &str) -> Result<Claims, Error> {
-    let decoded = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(SECRET),
-        &Validation::new(Algorithm::HS256),
-    )?;
-    Ok(decoded.claims)
+/// Signing and verification keys by `kid`, built once from the settings.
+/// Every token carries the `kid` of the key that signed it, so a new key
+/// can be rolled out while tokens of the old one stay valid.
+pub struct JwtKeys {
+    issuer: String,
+    audience: String,
+    signing: Option<(String, Algorithm, EncodingKey)>,
+    verifying: HashMap<String, (Algorithm, DecodingKey)>,
+    /// Verifies tokens without a `kid`, see `JwtSettings::legacy_secret`.
+    legacy: Option<DecodingKey>,
 }
 
-pub fn generate_token(username: &str, exp: Option<u64>) -> Result<String, jsonwebtoken::errors::Error> {
-    let expiration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() + exp.unwrap_or(3600);
+impl std::fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKeys")
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .field("signing_kid", &self.signing.as_ref().map(|(kid, _, _)| kid))
+            .field("kids", &self.verifying.keys().collect::<Vec<_>>())
+            .field("legacy", &self.legacy.is_some())
+            .finish()
+    }
+}
+
+fn read_pem(path: &Option<String>, kid: &str, what: &str) -> Result<Option<Vec<u8>>, String> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    std::fs::read(path)
+        .map(Some)
+        .map_err(|e| format!("JWT key {kid}: can not read {what} {path}: {e}"))
+}
+
+/// Encoding half (if any) and decoding half of one configured key.
+fn load_key(key: &JwtKeySettings) -> Result<(Algorithm, Option<EncodingKey>, DecodingKey), String> {
+    let kid = &key.kid;
+    let bad = |e: Error| format!("JWT key {kid}: {e}");
+    let algorithm = match key.algorithm.as_str() {
+        "HS256" => Algorithm::HS256,
+        "EdDSA" => Algorithm::EdDSA,
+        "RS256" => Algorithm::RS256,
+        other => return Err(format!("JWT key {kid}: unsupported algorithm {other}, use HS256, EdDSA or RS256")),
+    };
+
+    if algorithm == Algorithm::HS256 {
+        let secret = key.secret.as_deref().unwrap_or_default();
+        if secret.len() < MIN_SECRET_LEN {
+            return Err(format!("JWT key {kid}: HS256 secret must be at least {MIN_SECRET_LEN} bytes"));
+        }
+        return Ok((algorithm, Some(EncodingKey::from_secret(secret.as_bytes())), DecodingKey::from_secret(secret.as_bytes())));
+    }
+
+    let private = read_pem(&key.private_key_file, kid, "private key")?;
+    let public = read_pem(&key.public_key_file, kid, "public key")?
+        .ok_or(format!("JWT key {kid}: public_key_file is required for {}", key.algorithm))?;
+    let (encoding, decoding) = if algorithm == Algorithm::EdDSA {
+        (private.map(|pem| EncodingKey::from_ed_pem(&pem)).transpose().map_err(bad)?, DecodingKey::from_ed_pem(&public).map_err(bad)?)
+    } else {
+        (private.map(|pem| EncodingKey::from_rsa_pem(&pem)).transpose().map_err(bad)?, DecodingKey::from_rsa_pem(&public).map_err(bad)?)
+    };
+    Ok((algorithm, encoding, decoding))
+}
+
+impl JwtKeys {
+    pub fn from_settings(settings: &AppSettings) -> Result<Self, String> {
+        let jwt = &settings.jwt;
+        let keys = if jwt.keys.is_empty() {
+            vec![JwtKeySettings {
+                kid: DEFAULT_KID.to_string(),
+                algorithm: "HS256".to_string(),
+                secret: Some(settings.secret_key.clone()),
+                private_key_file: None,
+                public_key_file: None,
+            }]
+        } else {
+            jwt.keys.clone()
+        };
+
+        let mut signing = None;
+        let mut verifying = HashMap::new();
+        for key in &keys {
+            let (algorithm, encoding, decoding) = load_key(key)?;
+            if verifying.insert(key.kid.clone(), (algorithm, decoding)).is_some() {
+                return Err(format!("JWT key {} is configured twice", key.kid));
+            }
+            if key.kid == jwt.signing_kid {
+                let encoding = encoding.ok_or(format!("JWT signing key {} has no private key", key.kid))?;
+                signing = Some((key.kid.clone(), algorithm, encoding));
+            }
+        }
+        if signing.is_none() {
+            return Err(format!("JWT signing key {} is not among the configured keys", jwt.signing_kid));
+        }
+        // Старый секрет был короче MIN_SECRET_LEN, его длину не проверяем
+        let legacy = match jwt.legacy_secret.as_deref() {
+            Some("") => return Err("JWT legacy secret is empty".to_string()),
+            Some(secret) => Some(DecodingKey::from_secret(secret.as_bytes())),
+            None => None,
+        };
+
+        Ok(Self {
+            issuer: jwt.issuer.clone(),
+            audience: jwt.audience.clone(),
+            signing,
+            verifying,
+            legacy,
+        })
+    }
+
+    /// Checks the signature with the key named by the token's `kid`, the
+    /// expiry, the issuer and the audience. Tokens without a `kid` predate
+    /// all of that and are checked against the legacy secret, if one is
+    /// configured, by signature and expiry only.
+    pub fn validate_token(&self, token: &str) -> Result<Claims, Error> {
+        let Some(kid) = decode_header(token)?.kid else {
+            let key = self.legacy.as_ref().ok_or(ErrorKind::InvalidToken)?;
+            let mut validation = Validation::new(Algorithm::HS256);
+            validation.validate_aud = false;
+            validation.set_required_spec_claims(&["exp", "sub"]);
+            let mut claims = decode::<Claims>(token, key, &validation)?.claims;
+            // В таких токенах sub всегда имя пользователя
+            claims.ver = 0;
+            return Ok(claims);
+        };
+        let (algorithm, key) = self.verifying.get(&kid).ok_or(ErrorKind::InvalidKeyFormat)?;
+
+        let mut validation = Validation::new(*algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        Ok(decode::<Claims>(token, key, &validation)?.claims)
+    }
+
+    /// A token for the user with id `user_id` valid for `exp` seconds (one
+    /// hour by default).
+    pub fn generate_token(&self, user_id: i32, telegram_id: Option<&str>, exp: Option<u64>) -> Result<String, Error> {
+        let (kid, algorithm, key) = self.signing.as_ref().ok_or(ErrorKind::InvalidKeyFormat)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let claims = Claims {
+            exp: (now + exp.unwrap_or(3600)) as usize,
+            iat: now as usize,
+            sub: user_id.to_string(),
+            ver: CLAIMS_VERSION,
+            telegram_id: telegram_id.map(str::to_string),
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+        };
+
+        let mut header = Header::new(*algorithm);
+        header.kid = Some(kid.clone());
+        encode(&header, &claims, key)
+    }
+}
+
+/// The account a token belongs to.
+#[derive(Debug, Clone)]
+pub struct TokenUser {
+    pub id: i32,
+    /// Display name; not unique.
+    pub name: String,
+}
+
+/// Looks up the user of validated claims. Version 0 tokens name the user
+/// by username; those are matched like they used to be (the oldest
+/// account with that name) until they expire.
+pub async fn token_user(pool: &PgPool, claims: &Claims) -> Result<Option<TokenUser>, sqlx::Error> {
+    if claims.ver == 0 {
+        warn!("Legacy token with username subject {}", claims.sub);
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM users WHERE username = $1 AND NOT is_bot ORDER BY id LIMIT 1",
+            claims.sub
+        )
+        .fetch_optional(pool)
+        .await?;
+        return Ok(id.map(|id| TokenUser { id, name: claims.sub.clone() }));
+    }
 
-    let claims = Claims{
-        exp: expiration as usize,
-        sub: username.to_string(),
+    let Ok(id) = claims.sub.parse::<i32>() else {
+        warn!("Token of version {} with non-id subject {}", claims.ver, claims.sub);
+        return Ok(None);
     };
+    let user = sqlx::query!("SELECT id, username FROM users WHERE id = $1 AND NOT is_bot", id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(user.map(|u| TokenUser { id: u.id, name: u.username.unwrap_or_else(|| "anon".to_string()) }))
+}
+
+/// The user of an HTTP request's token, or the status to answer with.
+pub async fn authorize(pool: &PgPool, jwt: &JwtKeys, token: &str) -> Result<TokenUser, (StatusCode, &'static str)> {
+    let claims = jwt.validate_token(token).map_err(|err| {
+        error!("Invalid token: {:?}", err);
+        (StatusCode::UNAUTHORIZED, "Incorrect token")
+    })?;
+    let user = token_user(pool, &claims).await.map_err(|err| {
+        error!("DB error: {:?}", err);
+        (StatusCode::INTERNAL_SERVER_ERROR, "DB error")
+    })?;
+    user.ok_or((StatusCode::NOT_FOUND, "User not found"))
+}
 
-    // #TODO secret variable
-    encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET))
+/// Who is behind a WebSocket connection.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// `users.id` as text, the key of the player's sessions.
+    pub uid: String,
+    pub name: String,
+    pub is_bot: bool,
 }
 
-pub async fn handle_auth(auth_msg: Auth, write: &Arc<Mutex<impl SinkExt<Message> + Unpin + Send>>) -> Option<String> {
-    match validate_token(&auth_msg.token) {
-        Ok(claims) => {
+/// Accepts a user JWT or, for bot accounts, a long-lived API token.
+pub async fn handle_auth(auth_msg: Auth, db: &PgPool, jwt: &JwtKeys, write: &Arc<Mutex<impl SinkExt<Message> + Unpin + Send>>) -> Option<Identity> {
+    let identity = if is_api_token(&auth_msg.token) {
+        match validate_api_token(db, &auth_msg.token).await {
+            Ok(Some((id, name))) => Ok(Identity { uid: id.to_string(), name, is_bot: true }),
+            Ok(None) => Err("unknown or revoked API token".to_string()),
+            Err(err) => Err(format!("DB error: {err:?}")),
+        }
+    } else {
+        match jwt.validate_token(&auth_msg.token) {
+            Ok(claims) => match token_user(db, &claims).await {
+                Ok(Some(user)) => Ok(Identity { uid: user.id.to_string(), name: user.name, is_bot: false }),
+                Ok(None) => Err(format!("unknown user {}", claims.sub)),
+                Err(err) => Err(format!("DB error: {err:?}")),
+            },
+            Err(err) => Err(format!("{err:?}")),
+        }
+    };
+
+    match identity {
+        Ok(identity) => {
             // TODO  send like json object {"detail": "", "err_code"}
-            // println!("{:?}", claims);
-            let json = serde_json::to_string(&WSEvent::SuccessLogin { username: claims.sub.clone() }).unwrap();
+            let json = serde_json::to_string(&WSEvent::SuccessLogin {
+                user_id: identity.uid.clone(),
+                username: identity.name.clone(),
+                is_bot: identity.is_bot,
+            }).unwrap();
             let _ = write.lock().await.send(Message::Text(json)).await;
-            Some(claims.sub)
+            Some(identity)
         }
         Err(err) => {
-            error!("Invalid token: {:?}", err);
+            error!("Invalid token: {err}");
             let json = serde_json::to_string(&WSEvent::Error{detail: "Invalid token. Disconnecting.".to_string()}).unwrap();
             let _ = write.lock().await.send(Message::Text(json)).await;
             None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::db::testing::migrate_until;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+    /// The secret tokens were signed with before keys had a `kid`.
+    const LEGACY_SECRET: &str = "fuckyou";
+    /// Last migration before user ids replaced usernames.
+    const BEFORE_USER_IDS: i64 = 20261019095000;
+
+    fn keys() -> JwtKeys {
+        let mut settings = AppSettings { secret_key: SECRET.to_string(), ..AppSettings::default() };
+        settings.jwt.legacy_secret = Some(LEGACY_SECRET.to_string());
+        JwtKeys::from_settings(&settings).unwrap()
+    }
+
+    /// A token as the first release issued them: no `kid`, only `sub` and
+    /// `exp`, signed with the hard-coded secret.
+    fn baseline_token(username: &str, exp: u64) -> String {
+        let claims = serde_json::json!({ "sub": username, "exp": exp });
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(LEGACY_SECRET.as_bytes())).unwrap()
+    }
+
+    fn in_an_hour() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() + 3600
+    }
+
+    #[test]
+    fn new_tokens_carry_the_claims_version() {
+        let keys = keys();
+        let claims = keys.validate_token(&keys.generate_token(7, Some("100"), None).unwrap()).unwrap();
+        assert_eq!((claims.sub.as_str(), claims.ver), ("7", CLAIMS_VERSION));
+    }
+
+    #[test]
+    fn baseline_tokens_need_the_legacy_secret() {
+        let keys = keys();
+        let claims = keys.validate_token(&baseline_token("alice", in_an_hour())).unwrap();
+        assert_eq!((claims.sub.as_str(), claims.ver), ("alice", 0));
+        assert!(keys.validate_token(&baseline_token("alice", in_an_hour() - 7200)).is_err());
+
+        // Без kid, но подписан текущим секретом
+        let claims = serde_json::json!({ "sub": "alice", "exp": in_an_hour() });
+        let forged = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET.as_bytes())).unwrap();
+        assert!(keys.validate_token(&forged).is_err());
+
+        let settings = AppSettings { secret_key: SECRET.to_string(), ..AppSettings::default() };
+        let without_legacy = JwtKeys::from_settings(&settings).unwrap();
+        assert!(without_legacy.validate_token(&baseline_token("alice", in_an_hour())).is_err());
+    }
+
+    #[sqlx::test(migrations = false)]
+    #[ignore = "needs DATABASE_URL"]
+    async fn legacy_tokens_survive_user_id_migration(pool: PgPool) {
+        migrate_until(&pool, BEFORE_USER_IDS).await;
+        sqlx::query(
+            "INSERT INTO users (id, telegram_id, username) VALUES
+            (1, 't1', 'alice'), (2, 't2', 'alice'), (3, 't3', '42'), (42, 't42', 'zed')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let keys = keys();
+        let user = |token: String| {
+            let claims = keys.validate_token(&token).unwrap();
+            let pool = pool.clone();
+            async move { token_user(&pool, &claims).await.unwrap().map(|user| user.id) }
+        };
+        // A shared name goes to the oldest account, as before the migration
+        assert_eq!(user(baseline_token("alice", in_an_hour())).await, Some(1));
+        // A numeric username is still a username in a legacy token
+        assert_eq!(user(baseline_token("42", in_an_hour())).await, Some(3));
+        assert_eq!(user(baseline_token("nobody", in_an_hour())).await, None);
+
+        assert_eq!(user(keys.generate_token(42, None, None).unwrap()).await, Some(42));
+        assert_eq!(user(keys.generate_token(3, None, None).unwrap()).await, Some(3));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn versioned_tokens_never_fall_back_to_usernames(pool: PgPool) {
+        sqlx::query("INSERT INTO users (id, telegram_id, username) VALUES (1, 't1', 'alice')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let claims = Claims {
+            sub: "alice".to_string(),
+            ver: CLAIMS_VERSION,
+            telegram_id: None,
+            exp: 0,
+            iat: 0,
+            iss: String::new(),
+            aud: String::new(),
+        };
+        assert!(token_user(&pool, &claims).await.unwrap().is_none());
+    }
+}
@@ -1,5 +1,7 @@
 // pub mod redis;
 pub mod schemas;
+pub mod api_token;
 pub mod jwt;
+pub mod refresh_token;
 pub mod db;
 pub mod telegram;
@@ -0,0 +1,158 @@
+use rand::RngCore;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::warn;
+use crate::utils::api_token::hash_token;
+
+/// Refresh tokens start with this, so they are never mistaken for JWTs or bot tokens.
+pub const REFRESH_TOKEN_PREFIX: &str = "sqr_";
+/// How long a refresh token lives, in seconds (7 days).
+pub const REFRESH_TOKEN_TTL: u64 = 7 * 24 * 3600;
+
+#[derive(Debug, PartialEq)]
+pub enum RefreshError {
+    /// Unknown, expired or revoked token.
+    Invalid,
+    /// A token that was already rotated came back: someone holds a copy,
+    /// so the whole family has been revoked.
+    Reused,
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+async fn insert_token(tx: &mut Transaction<'_, Postgres>, user_id: i32, family_id: &str) -> Result<String, sqlx::Error> {
+    let token = format!("{REFRESH_TOKEN_PREFIX}{}", random_hex());
+    sqlx::query!(
+        "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(secs => $4))",
+        user_id,
+        family_id,
+        hash_token(&token),
+        REFRESH_TOKEN_TTL as f64
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(token)
+}
+
+/// Starts a new family (a login) and returns its first token.
+pub async fn issue_refresh_token(pool: &PgPool, user_id: i32) -> Result<String, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let token = insert_token(&mut tx, user_id, &random_hex()).await?;
+    tx.commit().await?;
+    Ok(token)
+}
+
+/// Trades a refresh token for the next one of its family. Returns the user
+/// id and the new token.
+pub async fn rotate_refresh_token(pool: &PgPool, token: &str) -> Result<Result<(i32, String), RefreshError>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query!(
+        r#"SELECT id, user_id, family_id, rotated_at IS NOT NULL AS "rotated!",
+            revoked_at IS NULL AND expires_at > now() AS "live!"
+        FROM refresh_tokens WHERE token_hash = $1
+        FOR UPDATE"#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(Err(RefreshError::Invalid));
+    };
+
+    if row.rotated {
+        warn!("Refresh token family {} reused, revoking it", row.family_id);
+        sqlx::query!(
+            "UPDATE refresh_tokens SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+            row.family_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        return Ok(Err(RefreshError::Reused));
+    }
+    if !row.live {
+        return Ok(Err(RefreshError::Invalid));
+    }
+
+    sqlx::query!("UPDATE refresh_tokens SET rotated_at = now() WHERE id = $1", row.id)
+        .execute(&mut *tx)
+        .await?;
+    let next = insert_token(&mut tx, row.user_id, &row.family_id).await?;
+    tx.commit().await?;
+    Ok(Ok((row.user_id, next)))
+}
+
+/// Logout: revokes the family of `token`. Returns how many of its tokens
+/// were not revoked yet, so a repeated logout is harmless.
+pub async fn revoke_refresh_family(pool: &PgPool, token: &str) -> Result<u64, sqlx::Error> {
+    let revoked = sqlx::query!(
+        "UPDATE refresh_tokens SET revoked_at = now()
+        WHERE revoked_at IS NULL
+            AND family_id = (SELECT family_id FROM refresh_tokens WHERE token_hash = $1)",
+        hash_token(token)
+    )
+    .execute(pool)
+    .await?;
+    Ok(revoked.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn user(pool: &PgPool) -> i32 {
+        sqlx::query_scalar("INSERT INTO users (telegram_id, username) VALUES ('1', 'alice') RETURNING id")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn rotation_hands_out_the_next_token(pool: PgPool) {
+        let user_id = user(&pool).await;
+
+        let first = issue_refresh_token(&pool, user_id).await.unwrap();
+        assert!(first.starts_with(REFRESH_TOKEN_PREFIX));
+        let (owner, second) = rotate_refresh_token(&pool, &first).await.unwrap().unwrap();
+        assert_eq!(owner, user_id);
+        assert_ne!(first, second);
+        assert!(rotate_refresh_token(&pool, &second).await.unwrap().is_ok());
+        assert_eq!(rotate_refresh_token(&pool, "sqr_unknown").await.unwrap(), Err(RefreshError::Invalid));
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn reusing_a_rotated_token_revokes_the_family(pool: PgPool) {
+        let user_id = user(&pool).await;
+
+        let stolen = issue_refresh_token(&pool, user_id).await.unwrap();
+        let (_, current) = rotate_refresh_token(&pool, &stolen).await.unwrap().unwrap();
+        let other_login = issue_refresh_token(&pool, user_id).await.unwrap();
+
+        assert_eq!(rotate_refresh_token(&pool, &stolen).await.unwrap(), Err(RefreshError::Reused));
+        // The legitimate holder is logged out too, other logins are not
+        assert_eq!(rotate_refresh_token(&pool, &current).await.unwrap(), Err(RefreshError::Invalid));
+        assert!(rotate_refresh_token(&pool, &other_login).await.unwrap().is_ok());
+    }
+
+    #[sqlx::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn logout_revokes_the_family(pool: PgPool) {
+        let user_id = user(&pool).await;
+
+        let first = issue_refresh_token(&pool, user_id).await.unwrap();
+        let (_, current) = rotate_refresh_token(&pool, &first).await.unwrap().unwrap();
+        let other_login = issue_refresh_token(&pool, user_id).await.unwrap();
+
+        // The rotated first token is stamped too
+        assert_eq!(revoke_refresh_family(&pool, &current).await.unwrap(), 2);
+        assert_eq!(rotate_refresh_token(&pool, &current).await.unwrap(), Err(RefreshError::Invalid));
+        assert_eq!(revoke_refresh_family(&pool, &current).await.unwrap(), 0);
+        assert!(rotate_refresh_token(&pool, &other_login).await.unwrap().is_ok());
+    }
+}
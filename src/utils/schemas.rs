@@ -1,11 +1,8 @@
 use serde::{Deserialize, Serialize};
-use tokio::sync::{RwLock, Mutex};
-use std::{sync::Arc, collections::HashMap};
-use futures_util::stream::SplitSink;
-use axum::extract::ws::WebSocket;
-use std::fmt;
+use std::collections::HashMap;
+use crate::ai::Difficulty;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 
 // use crate::core::engine::GameEngine;
 
@@ -19,9 +16,12 @@ pub enum Suit {
 
 impl Suit{
     pub fn random_suit() -> Suit {
-        let suits = vec![Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
-        let mut rng = thread_rng();
-        *suits.choose(&mut rng).expect("suits list is not empty")
+        Suit::random_suit_with(&mut thread_rng())
+    }
+
+    pub fn random_suit_with(rng: &mut impl Rng) -> Suit {
+        let suits = [Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
+        *suits.choose(rng).expect("suits list is not empty")
     }
 }
 
@@ -43,16 +43,18 @@ pub struct Card {
     pub rank: Rank,
 }
 
+/// Card points in one deal, 30 per suit.
+pub const DEAL_POINTS: u32 = 120;
+
 impl Card {
-    pub fn points(&self, trump: Suit) -> u8 {
-        match (self.rank, self.suit == trump) {
-            (Rank::Ace, _) => 11,
-            (Rank::Ten, _) => 10,
-            (Rank::King, _) => 4,
-            (Rank::Queen, _) => 3,
-            (Rank::Jack, true) => 20,
-            (Rank::Nine, true) => 14,
-            (Rank::Jack, false) => 2,
+    /// Card points: 30 per suit, 120 per deal. Trumps are worth no more.
+    pub fn points(&self) -> u8 {
+        match self.rank {
+            Rank::Ace => 11,
+            Rank::Ten => 10,
+            Rank::King => 4,
+            Rank::Queen => 3,
+            Rank::Jack => 2,
             _ => 0,
         }
     }
@@ -80,9 +82,13 @@ pub fn full_deck() -> Vec<Card> {
 }
 
 pub fn deal_cards() -> Vec<Vec<Card>> {
+    deal_cards_with(&mut thread_rng())
+}
+
+/// Deals from the given generator, so a seeded one reproduces the deal.
+pub fn deal_cards_with(rng: &mut impl Rng) -> Vec<Vec<Card>> {
     let mut deck = full_deck();
-    let mut rng = thread_rng();
-    deck.shuffle(&mut rng);
+    deck.shuffle(rng);
     deck.chunks(8).map(|chunk| chunk.to_vec()).collect()
 }
 
@@ -95,6 +101,32 @@ pub fn jack_priority(suit: Suit) -> u8 {
     }
 }
 
+/// Jacks are always trumps, above the trump suit itself.
+pub fn is_trump(card: Card, trump: Suit) -> bool {
+    card.rank == Rank::Jack || card.suit == trump
+}
+
+/// Strength of a card within a trick led with `lead_suit`.
+pub fn card_power(card: Card, lead_suit: Suit, trump: Suit) -> (u8, u8, Rank) {
+    if card.rank == Rank::Jack {
+        (3, jack_priority(card.suit), card.rank) // приоритет 3, валетный порядок
+    } else if card.suit == trump {
+        (2, 0, card.rank) // обычный козырь
+    } else if card.suit == lead_suit {
+        (1, 0, card.rank) // масть по взятке
+    } else {
+        (0, 0, card.rank) // остальное
+    }
+}
+
+/// Position currently taking a (possibly incomplete) trick.
+pub fn trick_winner(trick: &[(PlayerPosition, Card)], trump: Suit) -> Option<PlayerPosition> {
+    let lead_suit = trick.first()?.1.suit;
+    trick.iter()
+        .max_by_key(|(_, card)| card_power(*card, lead_suit, trump))
+        .map(|(pos, _)| *pos)
+}
+
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum PlayerPosition {
@@ -122,11 +154,28 @@ impl PlayerPosition {
     }
 }
 
-#[derive(Debug)]
+/// One deal of a game: the hands as dealt and every card played, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DealRecord {
+    pub trump: Suit,
+    pub hands: HashMap<PlayerPosition, Vec<Card>>,
+    pub plays: Vec<(PlayerPosition, Card)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameState {
     pub hands: HashMap<PlayerPosition, Vec<Card>>,
     pub trump: Suit,
     pub current_trick: Vec<(PlayerPosition, Card)>,
+    /// Every card played in the current deal, in order.
+    pub deal_plays: Vec<(PlayerPosition, Card)>,
+    /// Hands as they were dealt at the start of the current deal.
+    pub dealt_hands: HashMap<PlayerPosition, Vec<Card>>,
+    /// Cards each team has taken in tricks this deal.
+    pub taken: HashMap<u8, Vec<Card>>,
+    /// The last completed trick of this deal, in play order.
+    pub last_trick: Vec<(PlayerPosition, Card)>,
+    /// Card points of the current deal, kept equal to the taken piles.
     pub team_scores: HashMap<u8, u32>,
     pub team_eye: HashMap<u8, u32>,
     pub current_turn: PlayerPosition,
@@ -135,7 +184,11 @@ pub struct GameState {
 
 impl GameState {
     pub fn new(trump: Suit) -> Self {
-        let hands_vec = deal_cards();
+        GameState::new_with(trump, &mut thread_rng())
+    }
+
+    pub fn new_with(trump: Suit, rng: &mut impl Rng) -> Self {
+        let hands_vec = deal_cards_with(rng);
         let mut hands = HashMap::new();
         hands.insert(PlayerPosition::North, hands_vec[0].clone());
         hands.insert(PlayerPosition::East, hands_vec[1].clone());
@@ -143,9 +196,13 @@ impl GameState {
         hands.insert(PlayerPosition::West, hands_vec[3].clone());
 
         Self {
+            dealt_hands: hands.clone(),
             hands,
             trump,
             current_trick: vec![],
+            deal_plays: vec![],
+            taken: HashMap::from([(1, vec![]), (2, vec![])]),
+            last_trick: vec![],
             team_scores: HashMap::from([(1, 0), (2, 0)]),
             team_eye: HashMap::from([(1, 0), (2, 0)]),
             current_turn: PlayerPosition::North,
@@ -154,39 +211,93 @@ impl GameState {
     }
 
     pub fn update_hands(&mut self){
-        let hands_vec = deal_cards();
+        self.update_hands_with(&mut thread_rng());
+    }
+
+    pub fn update_hands_with(&mut self, rng: &mut impl Rng) {
+        let hands_vec = deal_cards_with(rng);
         let mut new_hands = HashMap::new();
         new_hands.insert(PlayerPosition::North, hands_vec[0].clone());
         new_hands.insert(PlayerPosition::East, hands_vec[1].clone());
         new_hands.insert(PlayerPosition::South, hands_vec[2].clone());
         new_hands.insert(PlayerPosition::West, hands_vec[3].clone());
 
+        self.dealt_hands = new_hands.clone();
         self.hands = new_hands;
+        self.deal_plays.clear();
+        self.taken = HashMap::from([(1, vec![]), (2, vec![])]);
+        self.last_trick.clear();
+        self.team_scores = HashMap::from([(1, 0), (2, 0)]);
+    }
+
+    /// Card points `team` has taken this deal.
+    pub fn deal_points(&self, team: u8) -> u32 {
+        self.taken.get(&team).map_or(0, |cards| cards.iter().map(|c| c.points() as u32).sum())
+    }
+
+    /// Tricks `team` has taken this deal.
+    pub fn tricks_taken(&self, team: u8) -> u32 {
+        self.taken.get(&team).map_or(0, |cards| cards.len() as u32 / 4)
+    }
+
+    /// A team that finished the deal without a single trick is "naked".
+    pub fn naked_team(&self) -> Option<u8> {
+        if self.hands.values().any(|h| !h.is_empty()) {
+            return None;
+        }
+        [1, 2].into_iter().find(|team| self.tricks_taken(*team) == 0)
+    }
+
+    pub fn last_trick_winner(&self) -> Option<PlayerPosition> {
+        if self.last_trick.len() != 4 {
+            return None;
+        }
+        trick_winner(&self.last_trick, self.trump)
+    }
+
+    /// Result of the current deal for the table.
+    pub fn round_summary(&self) -> WSRoundSummary {
+        WSRoundSummary {
+            trump: self.trump,
+            trump_team: self.trump_team(),
+            points: HashMap::from([(1, self.deal_points(1)), (2, self.deal_points(2))]),
+            tricks: HashMap::from([(1, self.tricks_taken(1)), (2, self.tricks_taken(2))]),
+            naked: self.naked_team(),
+        }
+    }
+
+    /// The current deal as dealt and played so far.
+    pub fn deal_record(&self) -> DealRecord {
+        DealRecord {
+            trump: self.trump,
+            hands: self.dealt_hands.clone(),
+            plays: self.deal_plays.clone(),
+        }
     }
 
     pub fn update_eye_after_round(&mut self) -> Option<u8> {
-        let a = self.team_scores.get(&1).copied().unwrap_or(0);
-        let b = self.team_scores.get(&2).copied().unwrap_or(0);
+        let a = self.deal_points(1);
+        let b = self.deal_points(2);
 
-        if a == 60 && b == 60 {
+        // Яйца: очки поровну, глаза никому
+        if a == DEAL_POINTS / 2 && b == DEAL_POINTS / 2 {
             return None;
         }
 
-        let (winner_team, loser_score, trump_team) = if a > b {
-            (1, b, 1)
+        let trump_team = self.trump_team();
+        let (winner_team, loser_score) = if a > b {
+            (1, b)
         } else {
-            (2, a, 1)
+            (2, a)
         };
 
         let mut eyes = 1;
 
-        if self.is_first_round {
-            eyes = 2;
-        } else if winner_team != trump_team {
+        if self.is_first_round || winner_team != trump_team {
             eyes = 2;
         }
 
-        if loser_score < 30 {
+        if loser_score < DEAL_POINTS / 4 {
             eyes += 1;
         }
 
@@ -198,6 +309,24 @@ impl GameState {
 
 
 
+    /// Team that plays "with the trump" and wins only one eye for an
+    /// ordinary deal. The rules always give it to team 1 (North/South).
+    pub fn trump_team(&self) -> u8 {
+        1
+    }
+
+    /// Cards `player` may play right now: the lead suit has to be followed.
+    pub fn legal_cards(&self, player: PlayerPosition) -> Vec<Card> {
+        let Some(hand) = self.hands.get(&player) else {
+            return vec![];
+        };
+        let Some((_, lead)) = self.current_trick.first() else {
+            return hand.clone();
+        };
+        let following: Vec<Card> = hand.iter().copied().filter(|c| c.suit == lead.suit).collect();
+        if following.is_empty() { hand.clone() } else { following }
+    }
+
     pub fn play_card(&mut self, player: PlayerPosition, card: Card) -> Result<(), &'static str> {
         if player != self.current_turn {
             return Err("Not your turn");
@@ -217,6 +346,7 @@ impl GameState {
 
         hand.retain(|&c| c != card);
         self.current_trick.push((player, card));
+        self.deal_plays.push((player, card));
         self.current_turn = self.current_turn.next();
         Ok(())
     }
@@ -227,32 +357,14 @@ impl GameState {
             return None;
         }
 
-        let lead_suit = self.current_trick[0].1.suit;
         let trump = self.trump;
-
-
-        let winner = self.current_trick.iter().max_by_key(|(_, card)| {
-            if card.rank == Rank::Jack {
-                (3, jack_priority(card.suit), card.rank.clone()) // приоритет 3, валетный порядок
-            } else if card.suit == self.trump {
-                (2, 0, card.rank.clone()) // обычный козырь
-            } else if card.suit == lead_suit {
-                (1, 0, card.rank.clone()) // масть по взятке
-            } else {
-                (0, 0, card.rank.clone()) // остальное
-            }
-        }).map(|(pos, _)| *pos).unwrap();
-
-        let trick_points: u32 = self
-            .current_trick
-            .iter()
-            .map(|(_, c)| c.points(trump) as u32)
-            .sum();
+        let winner = trick_winner(&self.current_trick, trump).unwrap();
 
         let team = winner.team();
-        *self.team_scores.entry(team).or_insert(0) += trick_points;
+        self.taken.entry(team).or_default().extend(self.current_trick.iter().map(|(_, c)| *c));
+        self.team_scores.insert(team, self.deal_points(team));
 
-        self.current_trick.clear();
+        self.last_trick = std::mem::take(&mut self.current_trick);
         self.current_turn = winner;
 
         Some(winner)
@@ -264,8 +376,14 @@ impl GameState {
 #[serde(tag = "event", rename_all = "snake_case")]
 pub enum WSEvent {
     PlayerDisconnected{ position: PlayerPosition },
-    SuccessLogin{ username: String },
+    PlayerReplacedByBot{ position: PlayerPosition },
+    PlayerReconnected{ position: PlayerPosition },
+    SuccessLogin{ user_id: String, username: String, is_bot: bool },
+    PartyWaiting{ partner: String },
+    PartyFormed{ partner: String },
+    LobbyUpdated{ code: String, host: String, seats: HashMap<PlayerPosition, SeatInfo> },
     GameStart { room_id: String, position: PlayerPosition },
+    Seats{ seats: HashMap<PlayerPosition, SeatInfo> },
     GameClose{reason: String},
     YourHand(WSYourHand),
     EyeUpdated{ team_a: u32, team_b: u32 },
@@ -273,10 +391,52 @@ pub enum WSEvent {
     YourTurn(WSYourTurn),
     CardPlayed(WSCardPlayed),
     TrickWon(WSTrickWon),
+    LastTrick{ winner: PlayerPosition, cards: Vec<(PlayerPosition, Card)> },
+    RoundSummary(WSRoundSummary),
     GameOver(WSGameOver),
+    RematchOffered{ seconds: u64 },
+    RematchVote{ position: PlayerPosition },
+    SeriesScore{ games: u32, wins: HashMap<PlayerPosition, u32> },
+    ResignProposed{ position: PlayerPosition },
+    ResignDeclined{ position: PlayerPosition },
+    TeamResigned{ team: u8 },
+    AbortVoteStarted{ position: PlayerPosition },
+    AbortVote{ position: PlayerPosition, accept: bool },
+    AbortVoteFailed,
+    GameAborted,
+    Hint{ card: Card, reason: String },
+    /// The server is going down for maintenance in about `seconds`.
+    Maintenance{ seconds: u64 },
     Error{detail: String},
 }
 
+/// Public matchmaking queues. Bot accounts are kept out of the rated one,
+/// so they can not farm rated games against people.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueKind {
+    /// People only, rated.
+    Rated,
+    /// People and bot accounts together, never rated.
+    Mixed,
+    /// Bot accounts only, rated among bots.
+    Bots,
+}
+
+impl QueueKind {
+    pub fn is_rated(&self) -> bool {
+        !matches!(self, QueueKind::Mixed)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeatInfo {
+    /// User id of a person or bot account, or the id of a server bot.
+    pub id: String,
+    pub name: String,
+    pub is_bot: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WSYourHand {
     pub cards: Vec<Card>,
@@ -297,6 +457,18 @@ pub struct WSTrickWon {
     pub position: PlayerPosition,
 }
 
+/// Sent when a deal ends, before the eyes are updated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WSRoundSummary {
+    pub trump: Suit,
+    /// Team that played with the trump, see `GameState::trump_team`.
+    pub trump_team: u8,
+    pub points: HashMap<u8, u32>,
+    pub tricks: HashMap<u8, u32>,
+    /// Team that took no trick at all.
+    pub naked: Option<u8>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WSGameOver {
     pub scores: HashMap<u8, u32>, // team_id -> score
@@ -312,6 +484,39 @@ pub struct SubManageMsg {
     pub rank: Option<String>,
     pub suit: Option<String>,
     pub room_id: Option<String>, // можно будет использовать для наблюдения
+    pub partner: Option<String>, // findgame: искать вместе с этим игроком
+    pub position: Option<PlayerPosition>, // joinroom: выбранное место
+    pub accept: Option<bool>, // rematch/resign/abort: согласие
+    pub swap_partners: Option<bool>, // rematch: сменить партнёров
+    pub fill_with_bots: Option<bool>, // startroom: посадить ботов на пустые места
+    pub difficulty: Option<Difficulty>, // startroom: сила ботов, без неё — простые боты
+    pub bot: Option<String>, // startroom: стратегия ботов по имени, например external:<name>
+    pub queue: Option<QueueKind>, // findgame: очередь, по умолчанию rated (для бот-аккаунтов — bots)
+}
+
+impl SubManageMsg {
+    /// Card of a `playcard` message, e.g. rank "10" and suit "h".
+    pub fn card(&self) -> Result<Card, &'static str> {
+        let rank = match self.rank.as_deref().map(str::to_lowercase).as_deref() {
+            Some("7") => Rank::Seven,
+            Some("8") => Rank::Eight,
+            Some("9") => Rank::Nine,
+            Some("10") => Rank::Ten,
+            Some("j") => Rank::Jack,
+            Some("q") => Rank::Queen,
+            Some("k") => Rank::King,
+            Some("a") => Rank::Ace,
+            _ => return Err("Invalid rank"),
+        };
+        let suit = match self.suit.as_deref().map(str::to_lowercase).as_deref() {
+            Some("c") => Suit::Clubs,
+            Some("d") => Suit::Diamonds,
+            Some("h") => Suit::Hearts,
+            Some("s") => Suit::Spades,
+            _ => return Err("Invalid suit"),
+        };
+        Ok(Card { rank, suit })
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -319,6 +524,13 @@ pub struct SubManageMsg {
 pub enum SubOrUnsub {
     FindGame(SubManageMsg),
     PlayCard(SubManageMsg),
+    JoinRoom(SubManageMsg),
+    LeaveRoom(SubManageMsg),
+    StartRoom(SubManageMsg),
+    Rematch(SubManageMsg),
+    Resign(SubManageMsg),
+    Abort(SubManageMsg),
+    Hint(SubManageMsg),
     Sub(SubManageMsg),
     UnSub(SubManageMsg),
 }
@@ -329,3 +541,107 @@ pub enum WSIncomingMessage {
     Manage(SubOrUnsub),
     Auth(Auth),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::core::manager::ALL_POSITIONS;
+
+    /// A played out deal where team 1 took `team_1` and team 2 the rest
+    /// of the deck.
+    fn played_out(team_1: &[Card], first_round: bool) -> GameState {
+        let mut state = GameState::new(Suit::Hearts);
+        state.hands = HashMap::from(ALL_POSITIONS.map(|pos| (pos, vec![])));
+        state.taken = HashMap::from([
+            (1, team_1.to_vec()),
+            (2, full_deck().into_iter().filter(|c| !team_1.contains(c)).collect()),
+        ]);
+        state.is_first_round = first_round;
+        state
+    }
+
+    fn suits(suits: &[Suit]) -> Vec<Card> {
+        full_deck().into_iter().filter(|c| suits.contains(&c.suit)).collect()
+    }
+
+    #[test]
+    fn a_deal_is_worth_120_points() {
+        let total: u32 = full_deck().iter().map(|c| c.points() as u32).sum();
+        assert_eq!(total, DEAL_POINTS);
+        let state = played_out(&suits(&[Suit::Clubs]), false);
+        assert_eq!(state.deal_points(1) + state.deal_points(2), 120);
+    }
+
+    #[test]
+    fn egg_gives_no_eyes() {
+        let mut state = played_out(&suits(&[Suit::Clubs, Suit::Spades]), false);
+        assert_eq!((state.deal_points(1), state.deal_points(2)), (60, 60));
+        assert_eq!(state.update_eye_after_round(), None);
+        assert_eq!((state.team_eye[&1], state.team_eye[&2]), (0, 0));
+    }
+
+    #[test]
+    fn trump_team_wins_one_eye_unless_loser_is_under_30() {
+        let mut state = played_out(&suits(&[Suit::Clubs, Suit::Spades, Suit::Diamonds]), false);
+        assert_eq!((state.deal_points(1), state.deal_points(2)), (90, 30));
+        assert_eq!(state.update_eye_after_round(), Some(1));
+        assert_eq!(state.team_eye[&1], 1);
+
+        let mut team_1 = suits(&[Suit::Clubs, Suit::Spades, Suit::Diamonds]);
+        team_1.push(Card { suit: Suit::Hearts, rank: Rank::Jack });
+        let mut state = played_out(&team_1, false);
+        assert_eq!((state.deal_points(1), state.deal_points(2)), (92, 28));
+        assert_eq!(state.update_eye_after_round(), Some(1));
+        assert_eq!(state.team_eye[&1], 2);
+    }
+
+    #[test]
+    fn other_team_and_first_deal_win_two_eyes() {
+        let mut state = played_out(&suits(&[Suit::Clubs]), false);
+        assert_eq!(state.update_eye_after_round(), Some(2));
+        assert_eq!((state.team_eye[&1], state.team_eye[&2]), (0, 2));
+
+        let mut state = played_out(&suits(&[Suit::Clubs, Suit::Spades, Suit::Diamonds]), true);
+        assert_eq!(state.update_eye_after_round(), Some(1));
+        assert_eq!(state.team_eye[&1], 2);
+        assert!(!state.is_first_round);
+    }
+
+    #[test]
+    fn tricks_go_to_the_winners_pile_and_reset_each_deal() {
+        let mut state = GameState::new_with(Suit::Hearts, &mut StdRng::seed_from_u64(3));
+        let mut winners = vec![];
+        while state.hands.values().any(|h| !h.is_empty()) {
+            let pos = state.current_turn;
+            state.play_card(pos, state.legal_cards(pos)[0]).unwrap();
+            if let Some(winner) = state.resolve_trick() {
+                let trick = &state.last_trick;
+                assert!(state.taken[&winner.team()].ends_with(&trick.iter().map(|(_, c)| *c).collect::<Vec<_>>()));
+                assert_eq!(state.team_scores[&1], state.deal_points(1));
+                assert_eq!(state.team_scores[&2], state.deal_points(2));
+                winners.push(winner.team());
+            }
+        }
+
+        assert_eq!(winners.len(), 8);
+        for team in [1, 2] {
+            let won = winners.iter().filter(|t| **t == team).count() as u32;
+            assert_eq!(state.tricks_taken(team), won);
+        }
+        let mut taken: Vec<Card> = state.taken.values().flatten().copied().collect();
+        let mut dealt: Vec<Card> = state.dealt_hands.values().flatten().copied().collect();
+        taken.sort_by_key(|c| (c.suit as u8, c.rank as u8));
+        dealt.sort_by_key(|c| (c.suit as u8, c.rank as u8));
+        assert_eq!(taken, dealt);
+        assert_eq!(state.deal_points(1) + state.deal_points(2), DEAL_POINTS);
+        assert_eq!(state.naked_team(), [1, 2].into_iter().find(|t| !winners.contains(t)));
+
+        // Новая сдача: стопки и очки обнуляются
+        state.update_hands_with(&mut StdRng::seed_from_u64(4));
+        assert_eq!(state.taken, HashMap::from([(1, vec![]), (2, vec![])]));
+        assert_eq!(state.team_scores, HashMap::from([(1, 0), (2, 0)]));
+        assert!(state.last_trick.is_empty() && state.deal_plays.is_empty());
+    }
+}
@@ -1,39 +1,147 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ed25519_dalek::{Signature, VerifyingKey};
 use hmac::{Hmac, Mac};
-use sha2::{Sha256, Digest};
+use sha2::{Digest, Sha256};
 use serde::Deserialize;
-use std::collections::{BTreeMap};
-use tracing::{info, warn, error};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use url::form_urlencoded;
-use hex;
 
-#[derive(Debug, Deserialize)]
+/// `WebAppUser` of the Mini App initData.
+#[derive(Debug, Clone, Deserialize)]
 pub struct TelegramUser {
     pub id: u64,
-    pub username: Option<String>,
+    #[serde(default)]
+    pub is_bot: bool,
     pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub language_code: Option<String>,
+    #[serde(default)]
+    pub is_premium: bool,
+    #[serde(default)]
+    pub added_to_attachment_menu: bool,
+    #[serde(default)]
+    pub allows_write_to_pm: bool,
     pub photo_url: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// `WebAppChat`: the chat the Mini App was opened from via the attachment menu.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramChat {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub title: String,
+    pub username: Option<String>,
+    pub photo_url: Option<String>,
+}
+
+/// Verified `WebAppInitData`.
+#[derive(Debug, Clone)]
 pub struct TelegramInitData {
-    pub user: TelegramUser,
+    pub query_id: Option<String>,
+    pub user: Option<TelegramUser>,
+    pub receiver: Option<TelegramUser>,
+    pub chat: Option<TelegramChat>,
+    pub chat_type: Option<String>,
+    pub chat_instance: Option<String>,
+    /// `startapp` parameter of the link the app was opened with.
+    pub start_param: Option<String>,
+    pub can_send_after: Option<u64>,
+    /// Unix time the data was signed at.
+    pub auth_date: u64,
 }
 
-type HmacSha256 = Hmac<Sha256>;
+#[derive(Debug, PartialEq)]
+pub enum TelegramAuthError {
+    MissingField(&'static str),
+    BadField(&'static str),
+    /// The hash or Ed25519 signature does not match.
+    BadSignature,
+    /// Signed longer ago than allowed, `age` seconds.
+    Expired { age: u64 },
+    /// Dated `ahead` seconds past now, more than clocks may drift apart.
+    FromTheFuture { ahead: u64 },
+}
 
-pub fn verify_telegram_auth(init_data: &str, bot_token: &str) -> Result<TelegramInitData, ()> {
-    let parsed = form_urlencoded::parse(init_data.as_bytes());
+impl fmt::Display for TelegramAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelegramAuthError::MissingField(field) => write!(f, "login data has no {field}"),
+            TelegramAuthError::BadField(field) => write!(f, "login data field {field} is malformed"),
+            TelegramAuthError::BadSignature => write!(f, "login data signature does not match"),
+            TelegramAuthError::Expired { age } => write!(f, "login data was signed {age}s ago"),
+            TelegramAuthError::FromTheFuture { ahead } => write!(f, "login data is dated {ahead}s in the future"),
+        }
+    }
+}
 
-    let mut params: BTreeMap<String, String> = BTreeMap::new();
-    let mut hash = String::new();
+impl std::error::Error for TelegramAuthError {}
 
-    for (key, value) in parsed {
-        if key == "hash" {
-            hash = value.to_string();
-        } else {
-            params.insert(key.to_string(), value.to_string());
-        }
+/// What the Telegram Login Widget hands to the page after login.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramWidgetAuth {
+    pub id: u64,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub photo_url: Option<String>,
+    pub auth_date: u64,
+    pub hash: String,
+}
+
+/// Telegram's Ed25519 key for bots of the test environment; the production
+/// one is `settings::TELEGRAM_PUBLIC_KEY`.
+pub const TELEGRAM_TEST_PUBLIC_KEY: &str = "40055058a4ee38156a06562e52eece92a771bcd8346a8c4615cb7376eddf72ec";
+
+/// How far ahead of our clock Telegram's may be.
+const MAX_CLOCK_SKEW_SECS: u64 = 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Accepts an `auth_date` no older than `max_age` and not in the future
+/// beyond the clock skew allowance.
+fn check_auth_date(auth_date: u64, max_age: Duration) -> Result<(), TelegramAuthError> {
+    let now = now_secs();
+    if auth_date > now + MAX_CLOCK_SKEW_SECS {
+        return Err(TelegramAuthError::FromTheFuture { ahead: auth_date - now });
+    }
+    let age = now.saturating_sub(auth_date);
+    if age > max_age.as_secs() {
+        return Err(TelegramAuthError::Expired { age });
     }
+    Ok(())
+}
+
+fn json_field<T: for<'de> Deserialize<'de>>(params: &BTreeMap<String, String>, key: &'static str) -> Result<Option<T>, TelegramAuthError> {
+    params
+        .get(key)
+        .map(|value| serde_json::from_str(value).map_err(|_| TelegramAuthError::BadField(key)))
+        .transpose()
+}
+
+fn number_field(params: &BTreeMap<String, String>, key: &'static str) -> Result<Option<u64>, TelegramAuthError> {
+    params
+        .get(key)
+        .map(|value| value.parse().map_err(|_| TelegramAuthError::BadField(key)))
+        .transpose()
+}
+
+/// Verifies the Mini App initData signed with `bot_token` and not older
+/// than `max_age`.
+pub fn verify_telegram_auth(init_data: &str, bot_token: &str, max_age: Duration) -> Result<TelegramInitData, TelegramAuthError> {
+    let mut params: BTreeMap<String, String> = form_urlencoded::parse(init_data.as_bytes())
+        .into_owned()
+        .collect();
+    let hash = params.remove("hash").ok_or(TelegramAuthError::MissingField("hash"))?;
+    let hash = hex::decode(hash).map_err(|_| TelegramAuthError::BadField("hash"))?;
 
     let data_check_string = params
         .iter()
@@ -42,27 +150,308 @@ pub fn verify_telegram_auth(init_data: &str, bot_token: &str) -> Result<Telegram
         .join("\n");
 
     //  secret_key = HMAC_SHA256("WebAppData", bot_token)
-    let mut secret_hmac = HmacSha256::new_from_slice(b"WebAppData").map_err(|_| ())?;
+    let mut secret_hmac = HmacSha256::new_from_slice(b"WebAppData").expect("HMAC takes keys of any size");
     secret_hmac.update(bot_token.as_bytes());
     let secret_key = secret_hmac.finalize().into_bytes();
 
-    //  hmac = HMAC_SHA256(data_check_string, secret_key)
-    let mut check_hmac = HmacSha256::new_from_slice(&secret_key).map_err(|_| ())?;
+    //  hmac = HMAC_SHA256(data_check_string, secret_key), сравнение за постоянное время
+    let mut check_hmac = HmacSha256::new_from_slice(&secret_key).expect("HMAC takes keys of any size");
+    check_hmac.update(data_check_string.as_bytes());
+    check_hmac.verify_slice(&hash).map_err(|_| TelegramAuthError::BadSignature)?;
+
+    parse_init_data(&params, max_age)
+}
+
+pub fn parse_public_key(hex_key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(format!("`{hex_key}` is not a 32-byte hex Ed25519 key"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("`{hex_key}`: {e}"))
+}
+
+/// Verifies the Ed25519 `signature` Telegram puts into initData for the
+/// bot `bot_id`. Needs no bot token, so any service can check who a user
+/// is; accepted if any of `public_keys` matches.
+pub fn verify_telegram_signature(init_data: &str, bot_id: u64, public_keys: &[VerifyingKey], max_age: Duration) -> Result<TelegramInitData, TelegramAuthError> {
+    let mut params: BTreeMap<String, String> = form_urlencoded::parse(init_data.as_bytes())
+        .into_owned()
+        .collect();
+    params.remove("hash");
+    let signature = params.remove("signature").ok_or(TelegramAuthError::MissingField("signature"))?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature.trim_end_matches('='))
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or(TelegramAuthError::BadField("signature"))?;
+
+    //  "{bot_id}:WebAppData\n" + отсортированные поля без hash и signature
+    let data_check_string = format!(
+        "{bot_id}:WebAppData\n{}",
+        params
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    if !public_keys.iter().any(|key| key.verify_strict(data_check_string.as_bytes(), &signature).is_ok()) {
+        return Err(TelegramAuthError::BadSignature);
+    }
+
+    parse_init_data(&params, max_age)
+}
+
+/// Fields of signature-checked initData, rejecting it when `auth_date` is
+/// older than `max_age` or in the future.
+fn parse_init_data(params: &BTreeMap<String, String>, max_age: Duration) -> Result<TelegramInitData, TelegramAuthError> {
+    let auth_date = number_field(params, "auth_date")?.ok_or(TelegramAuthError::MissingField("auth_date"))?;
+    check_auth_date(auth_date, max_age)?;
+
+    Ok(TelegramInitData {
+        query_id: params.get("query_id").cloned(),
+        user: json_field(params, "user")?,
+        receiver: json_field(params, "receiver")?,
+        chat: json_field(params, "chat")?,
+        chat_type: params.get("chat_type").cloned(),
+        chat_instance: params.get("chat_instance").cloned(),
+        start_param: params.get("start_param").cloned(),
+        can_send_after: number_field(params, "can_send_after")?,
+        auth_date,
+    })
+}
+
+/// Verifies Login Widget data. Unlike the Mini App, the widget signs with
+/// SHA-256 of the bot token as the key.
+pub fn verify_telegram_widget(auth: &TelegramWidgetAuth, bot_token: &str, max_age: Duration) -> Result<TelegramUser, TelegramAuthError> {
+    let hash = hex::decode(&auth.hash).map_err(|_| TelegramAuthError::BadField("hash"))?;
+
+    let mut fields = BTreeMap::new();
+    fields.insert("id", auth.id.to_string());
+    fields.insert("first_name", auth.first_name.clone());
+    fields.insert("auth_date", auth.auth_date.to_string());
+    for (key, value) in [("last_name", &auth.last_name), ("username", &auth.username), ("photo_url", &auth.photo_url)] {
+        if let Some(value) = value {
+            fields.insert(key, value.clone());
+        }
+    }
+    let data_check_string = fields
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    //  hmac = HMAC_SHA256(data_check_string, SHA256(bot_token))
+    let secret_key = Sha256::digest(bot_token.as_bytes());
+    let mut check_hmac = HmacSha256::new_from_slice(&secret_key).expect("HMAC takes keys of any size");
     check_hmac.update(data_check_string.as_bytes());
-    let calc_hash = hex::encode(check_hmac.finalize().into_bytes());
+    check_hmac.verify_slice(&hash).map_err(|_| TelegramAuthError::BadSignature)?;
+
+    check_auth_date(auth.auth_date, max_age)?;
+
+    Ok(TelegramUser {
+        id: auth.id,
+        is_bot: false,
+        first_name: auth.first_name.clone(),
+        last_name: auth.last_name.clone(),
+        username: auth.username.clone(),
+        language_code: None,
+        is_premium: false,
+        added_to_attachment_menu: false,
+        allows_write_to_pm: false,
+        photo_url: auth.photo_url.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOT_TOKEN: &str = "123456:TEST-bot-token";
+    const AUTH_DATE: u64 = 1760000000;
+    /// Mini App initData signed with `BOT_TOKEN` outside this crate.
+    const INIT_DATA: &str = "query_id=AAHdF6IQAAAAAN0XohDhrOrc&user=%7B%22id%22%3A279058397%2C%22first_name%22%3A%22Vladislav%22%2C%22last_name%22%3A%22Kibenko%22%2C%22username%22%3A%22vdkfrost%22%2C%22language_code%22%3A%22ru%22%2C%22is_premium%22%3Atrue%2C%22allows_write_to_pm%22%3Atrue%7D&auth_date=1760000000&start_param=room42&hash=ae217213d1848356c8b91f4efdb19d566de48ff18a3f35c39885349532f8e5fb";
+
+    /// An age limit the fixed `AUTH_DATE` is still within.
+    fn fresh() -> Duration {
+        Duration::from_secs(now_secs() - AUTH_DATE + 60)
+    }
+
+    #[test]
+    fn accepts_known_good_init_data() {
+        let data = verify_telegram_auth(INIT_DATA, BOT_TOKEN, fresh()).unwrap();
+        let user = data.user.unwrap();
+        assert_eq!((user.id, user.first_name.as_str()), (279058397, "Vladislav"));
+        assert_eq!(user.username.as_deref(), Some("vdkfrost"));
+        assert!(user.is_premium && user.allows_write_to_pm);
+        assert_eq!(data.query_id.as_deref(), Some("AAHdF6IQAAAAAN0XohDhrOrc"));
+        assert_eq!(data.start_param.as_deref(), Some("room42"));
+        assert_eq!(data.auth_date, AUTH_DATE);
+    }
 
-    if calc_hash == hash {
-        let user_json = params.get("user").ok_or(())?;
-        info!("json: {:?}", user_json);
+    #[test]
+    fn rejects_tampered_init_data() {
+        let tampered_hash = INIT_DATA.replace("hash=ae2172", "hash=ae2173");
+        assert_eq!(verify_telegram_auth(&tampered_hash, BOT_TOKEN, fresh()).unwrap_err(), TelegramAuthError::BadSignature);
 
-        let user : TelegramUser = serde_json::from_str(user_json).map_err(|_| ())?;
-        info!("json: {:?}", user);
+        let tampered_user = INIT_DATA.replace("279058397", "279058398");
+        assert_eq!(verify_telegram_auth(&tampered_user, BOT_TOKEN, fresh()).unwrap_err(), TelegramAuthError::BadSignature);
+
+        let other_bot = verify_telegram_auth(INIT_DATA, "654321:OTHER-bot-token", fresh());
+        assert_eq!(other_bot.unwrap_err(), TelegramAuthError::BadSignature);
+    }
+
+    #[test]
+    fn rejects_expired_init_data() {
+        let age = now_secs() - AUTH_DATE;
+        match verify_telegram_auth(INIT_DATA, BOT_TOKEN, Duration::from_secs(3600)) {
+            Err(TelegramAuthError::Expired { age: reported }) => assert!(reported >= age),
+            other => panic!("expected Expired, got {other:?}"),
+        }
+    }
+
+    /// initData for a minimal user signed with `BOT_TOKEN`, dated `auth_date`.
+    fn init_data_dated(auth_date: u64) -> String {
+        let mut secret = HmacSha256::new_from_slice(b"WebAppData").unwrap();
+        secret.update(BOT_TOKEN.as_bytes());
+        let mut hash = HmacSha256::new_from_slice(&secret.finalize().into_bytes()).unwrap();
+        hash.update(format!("auth_date={auth_date}\nuser={{\"id\":1,\"first_name\":\"A\"}}").as_bytes());
+        let hash = hex::encode(hash.finalize().into_bytes());
+        format!("user=%7B%22id%22%3A1%2C%22first_name%22%3A%22A%22%7D&auth_date={auth_date}&hash={hash}")
+    }
+
+    #[test]
+    fn rejects_init_data_from_the_future() {
+        let max_age = Duration::from_secs(3600);
+        assert!(verify_telegram_auth(&init_data_dated(now_secs()), BOT_TOKEN, max_age).is_ok());
+        // Часы Telegram могут чуть спешить
+        assert!(verify_telegram_auth(&init_data_dated(now_secs() + 30), BOT_TOKEN, max_age).is_ok());
+        match verify_telegram_auth(&init_data_dated(now_secs() + 3600), BOT_TOKEN, max_age) {
+            Err(TelegramAuthError::FromTheFuture { ahead }) => assert!(ahead > MAX_CLOCK_SKEW_SECS),
+            other => panic!("expected FromTheFuture, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_init_data_without_hash() {
+        let (unsigned, _) = INIT_DATA.split_once("&hash=").unwrap();
+        assert_eq!(verify_telegram_auth(unsigned, BOT_TOKEN, fresh()).unwrap_err(), TelegramAuthError::MissingField("hash"));
+        let garbled = format!("{unsigned}&hash=not-hex");
+        assert_eq!(verify_telegram_auth(&garbled, BOT_TOKEN, fresh()).unwrap_err(), TelegramAuthError::BadField("hash"));
+    }
+
+    /// Login Widget data for the same user, signed as the widget signs.
+    fn widget_auth(hash: &str) -> TelegramWidgetAuth {
+        TelegramWidgetAuth {
+            id: 279058397,
+            first_name: "Vladislav".to_string(),
+            last_name: None,
+            username: Some("vdkfrost".to_string()),
+            photo_url: Some("https://t.me/i/userpic/320/vdkfrost.jpg".to_string()),
+            auth_date: AUTH_DATE,
+            hash: hash.to_string(),
+        }
+    }
+
+    const WIDGET_HASH: &str = "0c0789697d847626d5c94883871ee96c723d72d0886012c2ed89f141b24f4fe7";
+    /// The same fields signed with the Mini App key, HMAC("WebAppData", token).
+    const WIDGET_HASH_WITH_WEBAPP_KEY: &str = "a2f39db52fba7d276e50ea0258c35692d6221857da0e46bae01147f4813661b1";
+
+    #[test]
+    fn accepts_known_good_widget_data() {
+        let user = verify_telegram_widget(&widget_auth(WIDGET_HASH), BOT_TOKEN, fresh()).unwrap();
+        assert_eq!((user.id, user.first_name.as_str()), (279058397, "Vladislav"));
+        assert_eq!(user.username.as_deref(), Some("vdkfrost"));
+        assert_eq!(user.photo_url.as_deref(), Some("https://t.me/i/userpic/320/vdkfrost.jpg"));
+    }
+
+    #[test]
+    fn rejects_widget_data_signed_with_the_webapp_key() {
+        let result = verify_telegram_widget(&widget_auth(WIDGET_HASH_WITH_WEBAPP_KEY), BOT_TOKEN, fresh());
+        assert_eq!(result.unwrap_err(), TelegramAuthError::BadSignature);
+    }
+
+    #[test]
+    fn rejects_tampered_or_expired_widget_data() {
+        let mut renamed = widget_auth(WIDGET_HASH);
+        renamed.username = Some("someone_else".to_string());
+        assert_eq!(verify_telegram_widget(&renamed, BOT_TOKEN, fresh()).unwrap_err(), TelegramAuthError::BadSignature);
+
+        let expired = verify_telegram_widget(&widget_auth(WIDGET_HASH), BOT_TOKEN, Duration::from_secs(3600));
+        assert!(matches!(expired, Err(TelegramAuthError::Expired { .. })));
+    }
+
+    const BOT_ID: u64 = 123456;
+    /// Seed of the key `SIGNED_INIT_DATA` is signed with: bytes 1..=32.
+    const SIGNING_SEED: [u8; 32] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32];
+    const SIGNING_PUBLIC_KEY: &str = "79b5562e8fe654f94078b112e8a98ba7901f853ae695bed7e0e3910bad049664";
+    /// initData for `BOT_ID` with an Ed25519 `signature`, made outside this crate.
+    const SIGNED_INIT_DATA: &str = "query_id=AAHdF6IQAAAAAN0XohDhrOrc&user=%7B%22id%22%3A279058397%2C%22first_name%22%3A%22Vladislav%22%2C%22username%22%3A%22vdkfrost%22%7D&auth_date=1760000000&hash=ae217213d1848356c8b91f4efdb19d566de48ff18a3f35c39885349532f8e5fb&signature=uhjb9VR1MCsuVwyqE9lcA9_ei-DebyNGf4httUzfsJZkvsic9K7PlkB8NG2mXArmXbtpJcy-iulEtMyJmV3WBw";
+
+    fn signing_key() -> VerifyingKey {
+        parse_public_key(SIGNING_PUBLIC_KEY).unwrap()
+    }
+
+    #[test]
+    fn accepts_known_good_signature() {
+        assert_eq!(ed25519_dalek::SigningKey::from_bytes(&SIGNING_SEED).verifying_key(), signing_key());
+        let data = verify_telegram_signature(SIGNED_INIT_DATA, BOT_ID, &[signing_key()], fresh()).unwrap();
+        assert_eq!(data.user.unwrap().id, 279058397);
+        assert_eq!(data.auth_date, AUTH_DATE);
+    }
+
+    #[test]
+    fn signs_bot_id_and_sorted_fields_without_hash() {
+        use ed25519_dalek::Signer;
+
+        let data_check_string = format!(
+            "{BOT_ID}:WebAppData\nauth_date={AUTH_DATE}\nquery_id=AAH\nuser={{\"id\":1,\"first_name\":\"A\"}}"
+        );
+        let signature = ed25519_dalek::SigningKey::from_bytes(&SIGNING_SEED).sign(data_check_string.as_bytes());
+        let init_data = format!(
+            "user=%7B%22id%22%3A1%2C%22first_name%22%3A%22A%22%7D&query_id=AAH&hash=00&auth_date={AUTH_DATE}&signature={}",
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        );
+        let data = verify_telegram_signature(&init_data, BOT_ID, &[signing_key()], fresh()).unwrap();
+        assert_eq!(data.user.unwrap().first_name, "A");
+    }
+
+    #[test]
+    fn rejects_signature_for_another_bot_or_key() {
+        let other_bot = verify_telegram_signature(SIGNED_INIT_DATA, BOT_ID + 1, &[signing_key()], fresh());
+        assert_eq!(other_bot.unwrap_err(), TelegramAuthError::BadSignature);
+
+        let other_key = ed25519_dalek::SigningKey::from_bytes(&[9; 32]).verifying_key();
+        let result = verify_telegram_signature(SIGNED_INIT_DATA, BOT_ID, &[other_key], fresh());
+        assert_eq!(result.unwrap_err(), TelegramAuthError::BadSignature);
+
+        let tampered = SIGNED_INIT_DATA.replace("279058397", "279058398");
+        let result = verify_telegram_signature(&tampered, BOT_ID, &[signing_key()], fresh());
+        assert_eq!(result.unwrap_err(), TelegramAuthError::BadSignature);
+
+        let (unsigned, _) = SIGNED_INIT_DATA.split_once("&signature=").unwrap();
+        let result = verify_telegram_signature(unsigned, BOT_ID, &[signing_key()], fresh());
+        assert_eq!(result.unwrap_err(), TelegramAuthError::MissingField("signature"));
+    }
 
+    #[test]
+    fn uses_the_configured_public_keys() {
+        use crate::config::settings::{AppSettings, TELEGRAM_PUBLIC_KEY};
 
-        let init_data = TelegramInitData { user };
+        let parse = |settings: AppSettings| -> Vec<VerifyingKey> {
+            settings.telegram_public_keys.iter().map(|key| parse_public_key(key).unwrap()).collect()
+        };
+        let defaults = parse(AppSettings::default());
+        assert_eq!(defaults, [parse_public_key(TELEGRAM_PUBLIC_KEY).unwrap()]);
+        let result = verify_telegram_signature(SIGNED_INIT_DATA, BOT_ID, &defaults, fresh());
+        assert_eq!(result.unwrap_err(), TelegramAuthError::BadSignature);
 
-        Ok(init_data)
-    } else {
-        Err(())
+        // Any configured key may match, whatever its place in the list
+        let settings = AppSettings {
+            telegram_public_keys: vec![TELEGRAM_TEST_PUBLIC_KEY.to_string(), SIGNING_PUBLIC_KEY.to_string()],
+            ..AppSettings::default()
+        };
+        let keys = parse(settings);
+        assert_eq!(keys.len(), 2);
+        assert!(verify_telegram_signature(SIGNED_INIT_DATA, BOT_ID, &keys, fresh()).is_ok());
     }
 }